@@ -0,0 +1,136 @@
+//! Compares the reading modes of `CsvReader` on a generated file that repeats the rows of
+//! `sample.csv` until it reaches `BENCH_MB` megabytes (64 by default), reading the file in
+//! chunks and then mapping it in memory (`MMAP=true`).
+//!
+//! The splitter handles 64 bytes at a time unless the dialect has an escape byte, so
+//! `next_row` also runs with `ESCAPE=\`, which `sample.csv` doesn't hold: the cells are
+//! the same, split one byte at a time.
+//!
+//! Run with `cargo bench`, or `BENCH_MB=1024 cargo bench` for a 1 GB file.
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use process_csv::{Config, CsvReader, YieldEvent};
+
+fn main() {
+    let size = env::var("BENCH_MB")
+        .ok()
+        .and_then(|val| val.parse::<usize>().ok())
+        .unwrap_or(64)
+        * 1024
+        * 1024;
+
+    let file_path = env::temp_dir().join("process_csv_bench.csv");
+    let sample = fs::read("sample.csv").expect("Run from the process_csv directory");
+    let mut data = Vec::with_capacity(size + sample.len());
+    while data.len() < size {
+        data.extend_from_slice(&sample);
+        data.push(b'\n');
+    }
+    fs::write(&file_path, &data).unwrap();
+    let file_path = file_path.to_string_lossy().into_owned();
+
+    run(
+        "process_file (owned cells)",
+        &file_path,
+        data.len(),
+        |reader| {
+            let mut cells = 0;
+            reader
+                .process_file(|event| {
+                    if let YieldEvent::NewCell(_) = event {
+                        cells += 1;
+                    }
+                })
+                .unwrap();
+            cells
+        },
+    );
+
+    run(
+        "records (owned rows)",
+        &file_path,
+        data.len(),
+        |mut reader| reader.records().map(|r| r.unwrap().len()).sum(),
+    );
+
+    run(
+        "next_row (borrowed cells)",
+        &file_path,
+        data.len(),
+        |mut reader| {
+            let mut cells = 0;
+            while let Some(row) = reader.next_row() {
+                cells += row.unwrap().len();
+            }
+            cells
+        },
+    );
+
+    run(
+        "par_records (owned rows)",
+        &file_path,
+        data.len(),
+        |mut reader| {
+            let rows = reader.par_records().unwrap();
+            rows.map(|r| r.unwrap().len()).sum()
+        },
+    );
+
+    // SAFETY: the benchmark is single threaded at this point, the workers of
+    // `par_records` are joined when it's dropped.
+    unsafe { env::set_var("ESCAPE", "\\") };
+
+    run(
+        "next_row (byte steps)",
+        &file_path,
+        data.len(),
+        |mut reader| {
+            let mut cells = 0;
+            while let Some(row) = reader.next_row() {
+                cells += row.unwrap().len();
+            }
+            cells
+        },
+    );
+
+    // SAFETY: as above.
+    unsafe {
+        env::remove_var("ESCAPE");
+        env::set_var("MMAP", "true");
+    }
+
+    run("records (mmap)", &file_path, data.len(), |mut reader| {
+        reader.records().map(|r| r.unwrap().len()).sum()
+    });
+
+    run("next_row (mmap)", &file_path, data.len(), |mut reader| {
+        let mut cells = 0;
+        while let Some(row) = reader.next_row() {
+            cells += row.unwrap().len();
+        }
+        cells
+    });
+
+    fs::remove_file(file_path).unwrap();
+}
+
+fn run<F>(name: &str, file_path: &str, bytes: usize, read: F)
+where
+    F: Fn(CsvReader) -> usize,
+{
+    let mut best = Duration::MAX;
+    let mut cells = 0;
+
+    for _ in 0..5 {
+        let args = ["bench".to_string(), file_path.to_string()].into_iter();
+        let reader = CsvReader::build_from(Config::build_from(args).unwrap()).unwrap();
+
+        let start = Instant::now();
+        cells = read(reader);
+        best = best.min(start.elapsed());
+    }
+
+    let throughput = bytes as f64 / best.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{name:<28} {cells:>10} cells  {best:>12.2?}  {throughput:>8.1} MB/s");
+}
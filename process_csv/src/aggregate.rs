@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::headers::column_of;
+use crate::{BorrowedRecord, CellParser, CsvError, CsvReader};
+
+/// Aggregation applied to the values of each group by `group_by`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agg {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl FromStr for Agg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Agg::Count),
+            "sum" => Ok(Agg::Sum),
+            "avg" => Ok(Agg::Avg),
+            "min" => Ok(Agg::Min),
+            "max" => Ok(Agg::Max),
+            _ => Err("Expected one of count, sum, avg, min or max"),
+        }
+    }
+}
+
+impl fmt::Display for Agg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Agg::Count => "count",
+            Agg::Sum => "sum",
+            Agg::Avg => "avg",
+            Agg::Min => "min",
+            Agg::Max => "max",
+        };
+        f.pad(name)
+    }
+}
+
+/// Running count, sum, minimum and maximum of a series of numbers, in constant memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Accumulator {
+    count: u64,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Accumulator {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Calculates the average of the values added so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use process_csv::Accumulator;
+    ///
+    /// let mut values = Accumulator::default();
+    /// values.add(1.0);
+    /// values.add(2.0);
+    /// values.add(3.0);
+    /// assert_eq!(values.average(), Some(2.0));
+    /// ```
+    ///
+    /// If no values were added, the average returns `None`.
+    pub fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    /// Returns the aggregation asked for, `None` for the ones undefined without values.
+    pub fn get(&self, agg: Agg) -> Option<f64> {
+        match agg {
+            Agg::Count => Some(self.count as f64),
+            Agg::Sum => Some(self.sum),
+            Agg::Avg => self.average(),
+            Agg::Min => self.min,
+            Agg::Max => self.max,
+        }
+    }
+}
+
+/// Counts the remaining rows of `reader`, the header row excluded.
+pub fn count<R: Read>(reader: &mut CsvReader<R>) -> Result<u64, CsvError> {
+    reader.headers()?;
+
+    let mut rows = 0;
+    while let Some(row) = reader.next_row() {
+        row?;
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// Groups the remaining rows of `reader` by the values of the `key` column and aggregates
+/// the numbers of the `of` column in each group. Memory grows with the number of groups,
+/// not with the number of rows.
+///
+/// Columns are named after the header, or given by 0-based index. Null cells, as defined
+/// by `CellParser`, are skipped. `Agg::Count` counts the rows of each group when `of` is
+/// `None`, and the non-null values of `of` otherwise.
+///
+/// # Returns
+/// - The groups sorted by key, with the aggregated value, `None` for the average, minimum
+///   or maximum of a group without values.
+/// - `Err(CsvError::UnknownColumn)` when a column isn't in the header.
+/// - `Err(CsvError::InvalidArgument)` when `of` is `None` with an aggregation other than
+///   `Agg::Count`.
+/// - `Err(CsvError)` when a value of `of` isn't a number.
+///
+/// # Example
+/// ```
+/// use process_csv::{Agg, CsvReader, Dialect, group_by};
+///
+/// let content = b"Country,Age\nUK,30\nUSA,25\nUK,40\n";
+/// let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+///
+/// let groups = group_by(&mut reader, "Country", Agg::Avg, Some("Age")).unwrap();
+/// assert_eq!(groups, vec![("UK".to_string(), Some(35.0)), ("USA".to_string(), Some(25.0))]);
+/// ```
+pub fn group_by<R: Read>(
+    reader: &mut CsvReader<R>,
+    key: &str,
+    agg: Agg,
+    of: Option<&str>,
+) -> Result<Vec<(String, Option<f64>)>, CsvError> {
+    let parser = CellParser::from(reader.dialect());
+    let headers = reader.headers()?.cloned();
+    let key = column_of(headers.as_ref(), key)?;
+    let of = match of {
+        Some(of) => Some(column_of(headers.as_ref(), of)?),
+        None if agg == Agg::Count => None,
+        None => {
+            let message = format!("{agg} needs a column of values");
+            return Err(CsvError::InvalidArgument(message));
+        }
+    };
+
+    let mut groups: BTreeMap<String, (u64, Accumulator)> = BTreeMap::new();
+    while let Some(row) = reader.next_row() {
+        let row = row?;
+        let group = cell_of(&row, key, &parser)?;
+
+        let value = match of {
+            Some(of) => {
+                let raw = row.get(of).unwrap_or_default();
+                parser
+                    .to_option(raw.to_vec(), CellParser::to_float)
+                    .map_err(|e| e.at(row.position()).in_cell(of, None, raw))?
+            }
+            None => None,
+        };
+
+        let (rows, values) = groups.entry(group).or_default();
+        *rows += 1;
+        if let Some(value) = value {
+            values.add(value);
+        }
+    }
+
+    let groups = groups
+        .into_iter()
+        .map(|(group, (rows, values))| match (agg, of) {
+            (Agg::Count, None) => (group, Some(rows as f64)),
+            _ => (group, values.get(agg)),
+        })
+        .collect();
+    Ok(groups)
+}
+
+/// Lists the distinct values of `column` in the remaining rows of `reader`, in the order
+/// they first appear.
+pub fn distinct<R: Read>(reader: &mut CsvReader<R>, column: &str) -> Result<Vec<String>, CsvError> {
+    let parser = CellParser::from(reader.dialect());
+    let column = column_of(reader.headers()?, column)?;
+
+    let mut seen = HashSet::new();
+    let mut values = Vec::new();
+    while let Some(row) = reader.next_row() {
+        let value = cell_of(&row?, column, &parser)?;
+        if seen.insert(value.clone()) {
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+/// Counts the values of `column` in the remaining rows of `reader`.
+///
+/// # Parameters
+/// - `width`: Groups numbers into buckets of this width, labelled with their lower and
+///   upper bounds like `20..30`, rather than counting each distinct value. Null cells are
+///   left out then.
+///
+/// # Returns
+/// - The values and their counts, from the most to the least frequent, or the buckets in
+///   ascending order when `width` is set.
+pub fn histogram<R: Read>(
+    reader: &mut CsvReader<R>,
+    column: &str,
+    width: Option<f64>,
+) -> Result<Vec<(String, u64)>, CsvError> {
+    let parser = CellParser::from(reader.dialect());
+    let column = column_of(reader.headers()?, column)?;
+
+    let Some(width) = width else {
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        while let Some(row) = reader.next_row() {
+            *counts.entry(cell_of(&row?, column, &parser)?).or_default() += 1;
+        }
+
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+        return Ok(counts);
+    };
+
+    let mut buckets: BTreeMap<i64, u64> = BTreeMap::new();
+    while let Some(row) = reader.next_row() {
+        let row = row?;
+        let raw = row.get(column).unwrap_or_default();
+        let value = parser
+            .to_option(raw.to_vec(), CellParser::to_float)
+            .map_err(|e| e.at(row.position()).in_cell(column, None, raw))?;
+
+        if let Some(value) = value {
+            *buckets.entry((value / width).floor() as i64).or_default() += 1;
+        }
+    }
+
+    let buckets = buckets
+        .into_iter()
+        .map(|(bucket, count)| {
+            let low = bucket as f64 * width;
+            (format!("{low}..{}", low + width), count)
+        })
+        .collect();
+    Ok(buckets)
+}
+
+/// Unquoted and trimmed value of the cell, empty when the row is shorter.
+fn cell_of(row: &BorrowedRecord, column: usize, parser: &CellParser) -> Result<String, CsvError> {
+    let raw = row.get(column).unwrap_or_default();
+    parser
+        .to_string(raw.to_vec())
+        .map_err(|e| e.at(row.position()).in_cell(column, None, raw))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    const USERS: &[u8] = b"Name,Age,Country\n\
+        Alice,25,USA\n\
+        Bob,30,UK\n\
+        Carol,NA,UK\n\
+        \"Dave\",42,\"USA\"\n\
+        Eve,19,France\n";
+
+    fn reader() -> CsvReader<&'static [u8]> {
+        CsvReader::from_reader(USERS, Dialect::default())
+    }
+
+    #[test]
+    fn accumulator() {
+        let mut values = Accumulator::default();
+        assert_eq!(
+            (values.average(), values.min(), values.get(Agg::Count)),
+            (None, None, Some(0.0))
+        );
+
+        for value in [3.0, -1.0, 4.0] {
+            values.add(value);
+        }
+        assert_eq!(values.count(), 3);
+        assert_eq!(values.sum(), 6.0);
+        assert_eq!(values.average(), Some(2.0));
+        assert_eq!((values.min(), values.max()), (Some(-1.0), Some(4.0)));
+    }
+
+    #[test]
+    fn groups() {
+        let sums = group_by(&mut reader(), "Country", Agg::Sum, Some("Age")).unwrap();
+        assert_eq!(
+            sums,
+            vec![
+                ("France".to_string(), Some(19.0)),
+                ("UK".to_string(), Some(30.0)),
+                ("USA".to_string(), Some(67.0)),
+            ]
+        );
+
+        let counts = group_by(&mut reader(), "Country", Agg::Count, None).unwrap();
+        assert_eq!(counts[1], ("UK".to_string(), Some(2.0)));
+        let counts = group_by(&mut reader(), "Country", Agg::Count, Some("Age")).unwrap();
+        assert_eq!(counts[1], ("UK".to_string(), Some(1.0)));
+
+        let maxima = group_by(&mut reader(), "2", Agg::Max, Some("1")).unwrap();
+        assert_eq!(maxima[2], ("USA".to_string(), Some(42.0)));
+    }
+
+    #[test]
+    fn group_errors() {
+        let e = group_by(&mut reader(), "City", Agg::Count, None).unwrap_err();
+        assert_eq!(e.to_string(), "No column named \"City\"");
+        let e = group_by(&mut reader(), "Country", Agg::Avg, None).unwrap_err();
+        assert!(matches!(e, CsvError::InvalidArgument(_)));
+        assert_eq!(
+            e.to_string(),
+            "Invalid argument: avg needs a column of values"
+        );
+
+        let e = group_by(&mut reader(), "Country", Agg::Avg, Some("Name")).unwrap_err();
+        assert_eq!(e.position().unwrap().record, 1);
+    }
+
+    #[test]
+    fn distinct_values() {
+        assert_eq!(
+            distinct(&mut reader(), "Country").unwrap(),
+            vec!["USA", "UK", "France"]
+        );
+        assert_eq!(count(&mut reader()).unwrap(), 5);
+    }
+
+    #[test]
+    fn histograms() {
+        let counts = histogram(&mut reader(), "Country", None).unwrap();
+        let counts: Vec<(&str, u64)> = counts.iter().map(|(v, n)| (v.as_str(), *n)).collect();
+        assert_eq!(counts, vec![("UK", 2), ("USA", 2), ("France", 1)]);
+
+        let buckets = histogram(&mut reader(), "Age", Some(10.0)).unwrap();
+        let buckets: Vec<(&str, u64)> = buckets.iter().map(|(v, n)| (v.as_str(), *n)).collect();
+        assert_eq!(
+            buckets,
+            vec![("10..20", 1), ("20..30", 1), ("30..40", 1), ("40..50", 1)]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+/// `AsyncCsvReader` reads rows from an `AsyncRead`, such as a tokio file or socket, as a
+/// `Stream` of records. Enabled with the `async` feature.
+///
+/// It is a `CsvReader` over an input that polls the `AsyncRead` with the waker of the
+/// task, so rows are split by the same state machine, with the same dialects, recovery
+/// policies and positions. When the input isn't ready, the reader stops with a
+/// `WouldBlock` error that the stream turns into `Poll::Pending`, and picks up where it
+/// stopped once the task is woken.
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{Anomaly, CsvError, CsvReader, Dialect, Record, Recovery};
+
+pub struct AsyncCsvReader<R> {
+    reader: CsvReader<Polled<R>>,
+}
+
+/// Blocking view of an `AsyncRead`, polled with the waker of the last `poll_next`.
+struct Polled<R> {
+    input: R,
+    waker: Option<Waker>,
+    pending: bool, // the last read found the input not ready
+}
+
+impl<R: AsyncRead + Unpin> Read for Polled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(waker) = &self.waker else {
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+        let mut cx = Context::from_waker(waker);
+        let mut buf = ReadBuf::new(buf);
+
+        match Pin::new(&mut self.input).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(Ok(())) => Ok(buf.filled().len()),
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => {
+                self.pending = true;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncCsvReader<R> {
+    /// Builds a reader over any asynchronous byte source. The first row is taken as the
+    /// header row, see `without_headers`.
+    ///
+    /// # Example
+    /// ```
+    /// # async fn run() -> Result<(), process_csv::CsvError> {
+    /// use futures_core::Stream;
+    /// use process_csv::{AsyncCsvReader, Dialect};
+    /// use std::future::poll_fn;
+    /// use std::pin::Pin;
+    ///
+    /// let content = b"Name,Age\nAlice,25\nBob,30\n";
+    /// let mut reader = AsyncCsvReader::from_reader(&content[..], Dialect::default());
+    ///
+    /// while let Some(record) = poll_fn(|cx| Pin::new(&mut reader).poll_next(cx)).await {
+    ///     println!("{:?}", record?.get_by_name("Name"));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_reader(input: R, dialect: Dialect) -> Self {
+        let input = Polled {
+            input,
+            waker: None,
+            pending: false,
+        };
+        AsyncCsvReader {
+            reader: CsvReader::from_reader(input, dialect),
+        }
+    }
+
+    /// Reads the first row as a row like the others, see `CsvReader::without_headers`.
+    pub fn without_headers(mut self) -> Self {
+        self.reader = self.reader.without_headers();
+        self
+    }
+
+    /// Sets the size of the chunks read from the input.
+    pub fn with_watermark(mut self, watermark: usize) -> Self {
+        self.reader = self.reader.with_watermark(watermark);
+        self
+    }
+
+    /// Sets what happens to the rows holding a malformed quote, see `CsvReader::with_recovery`.
+    pub fn with_recovery(mut self, recovery: Recovery) -> Self {
+        self.reader = self.reader.with_recovery(recovery);
+        self
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.reader.dialect()
+    }
+
+    /// Malformed quotes found so far, see `CsvReader::anomalies`.
+    pub fn anomalies(&self) -> &[Anomaly] {
+        self.reader.anomalies()
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncCsvReader<R> {
+    type Item = Result<Record, CsvError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let reader = &mut self.get_mut().reader;
+        let input = reader.input_mut();
+        input.pending = false;
+        if !input
+            .waker
+            .as_ref()
+            .is_some_and(|w| w.will_wake(cx.waker()))
+        {
+            input.waker = Some(cx.waker().clone());
+        }
+
+        let row = reader.next_row().map(|row| row.map(|row| row.to_record()));
+        match row {
+            Some(Err(CsvError::Io(e)))
+                if e.kind() == io::ErrorKind::WouldBlock && reader.input_mut().pending =>
+            {
+                Poll::Pending
+            }
+            row => Poll::Ready(row),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An input handing out a few bytes at a time, not ready every other poll.
+    struct Trickle<'a> {
+        content: &'a [u8],
+        ready: bool,
+    }
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
+            let n = self.content.len().min(buf.remaining()).min(3);
+            buf.put_slice(&self.content[..n]);
+            self.content = &self.content[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Polls `stream` to the end, as an executor would, counting the pending polls.
+    fn collect<S: Stream + Unpin>(mut stream: S) -> (Vec<S::Item>, usize) {
+        let mut cx = Context::from_waker(Waker::noop());
+        let (mut items, mut pending) = (Vec::new(), 0);
+
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return (items, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    fn describe(row: &Result<Record, CsvError>) -> String {
+        match row {
+            Ok(record) => format!(
+                "{:?} at {:?}",
+                record.iter().collect::<Vec<_>>(),
+                record.position()
+            ),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn same_rows_as_sync() {
+        let content = b"Name,Note\nAlice,\"two\r\nlines\"\r\nBob,\"a \"\"b\"\"\"\nCarol,x,y\n";
+        let expected: Vec<String> = CsvReader::from_reader(&content[..], Dialect::default())
+            .records()
+            .map(|row| describe(&row))
+            .collect();
+
+        let input = Trickle {
+            content,
+            ready: false,
+        };
+        let reader = AsyncCsvReader::from_reader(input, Dialect::default()).with_watermark(4);
+        let (rows, pending) = collect(reader);
+
+        let rows: Vec<String> = rows.iter().map(describe).collect();
+        assert_eq!(rows, expected);
+        assert!(pending > 0);
+    }
+
+    #[test]
+    fn recovery_and_headers() {
+        let input = Trickle {
+            content: b"Item,Size\nTV,55\" screen\nRadio,small\n",
+            ready: false,
+        };
+        let mut reader =
+            AsyncCsvReader::from_reader(input, Dialect::default()).with_recovery(Recovery::SkipRow);
+
+        let (rows, _) = collect(&mut reader);
+
+        assert_eq!(rows.len(), 1);
+        let radio = rows[0].as_ref().unwrap();
+        assert_eq!(radio.get_by_name("Size"), Some(&b"small"[..]));
+        assert_eq!(
+            reader.anomalies()[0].to_string(),
+            "Stray quote on line 2, byte 15, in record 1"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression formats `CsvReader::build_from` decompresses on the fly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the format from the first bytes of the input, or from the extension of its
+    /// path (`.gz` or `.zst`) when they don't match any format.
+    pub fn detect(magic: &[u8], path: Option<&Path>) -> Compression {
+        if magic.starts_with(GZIP_MAGIC) {
+            return Compression::Gzip;
+        }
+        if magic.starts_with(ZSTD_MAGIC) {
+            return Compression::Zstd;
+        }
+
+        match path.and_then(Path::extension).and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Detects the format of `input` and wraps it in a streaming decoder. The bytes read
+    /// to detect the format are put back in front of the input.
+    ///
+    /// # Parameters
+    /// - `input`: The raw input.
+    /// - `path`: The file the input comes from, if any, whose extension is used as a fallback.
+    ///
+    /// # Returns
+    /// - The detected format and the decompressed input.
+    pub fn decompress<R>(
+        mut input: R,
+        path: Option<&Path>,
+    ) -> io::Result<(Compression, Box<dyn Read + Send>)>
+    where
+        R: Read + Send + 'static,
+    {
+        let mut magic = [0; 4];
+        let n = read_prefix(&mut input, &mut magic)?;
+
+        let compression = Compression::detect(&magic[..n], path);
+        let input = Cursor::new(magic).take(n as u64).chain(input);
+
+        let input: Box<dyn Read + Send> = match compression {
+            Compression::None => Box::new(input),
+            Compression::Gzip => Box::new(MultiGzDecoder::new(input)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(input)?),
+        };
+
+        Ok((compression, input))
+    }
+}
+
+/// Reads the first bytes of `input` into `buf`, as many as fit unless the input is shorter.
+pub(crate) fn read_prefix<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match input.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::{GzBuilder, write::GzEncoder};
+    use std::io::Write;
+
+    const CSV: &[u8] = b"Name,Age\nBob,30\n";
+
+    fn read(input: Vec<u8>, path: Option<&str>) -> (Compression, Vec<u8>) {
+        let (compression, mut input) =
+            Compression::decompress(Cursor::new(input), path.map(Path::new)).unwrap();
+        let mut content = Vec::new();
+        input.read_to_end(&mut content).unwrap();
+        (compression, content)
+    }
+
+    #[test]
+    fn detect_by_magic_bytes() {
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(CSV).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(
+            read(gzip, Some("export.csv")),
+            (Compression::Gzip, CSV.to_vec())
+        );
+
+        let zstd = zstd::encode_all(CSV, 0).unwrap();
+        assert_eq!(read(zstd, None), (Compression::Zstd, CSV.to_vec()));
+
+        assert_eq!(read(CSV.to_vec(), None), (Compression::None, CSV.to_vec()));
+        assert_eq!(
+            read(b"a".to_vec(), None),
+            (Compression::None, b"a".to_vec())
+        );
+    }
+
+    #[test]
+    fn detect_by_extension() {
+        let path = Some(Path::new("export.csv.zst"));
+        assert_eq!(Compression::detect(b"", path), Compression::Zstd);
+
+        let path = Some(Path::new("export.csv.gz"));
+        assert_eq!(Compression::detect(b"Name", path), Compression::Gzip);
+        assert_eq!(Compression::detect(b"Name", None), Compression::None);
+    }
+
+    #[test]
+    fn concatenated_gzip_members() {
+        let mut members = Vec::new();
+        for part in [&CSV[..9], &CSV[9..]] {
+            let mut gzip = GzBuilder::new().write(Vec::new(), flate2::Compression::fast());
+            gzip.write_all(part).unwrap();
+            members.extend(gzip.finish().unwrap());
+        }
+
+        assert_eq!(read(members, None), (Compression::Gzip, CSV.to_vec()));
+    }
+}
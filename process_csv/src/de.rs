@@ -0,0 +1,344 @@
+use std::io::Read;
+use std::marker::PhantomData;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Error as _, IntoDeserializer, MapAccess, SeqAccess,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::{CellParser, CsvError, CsvReader, Headers, Record};
+
+/// Iterator over the rows of a `CsvReader` deserialized into `T`, see `CsvReader::deserialize`.
+pub struct DeserializeRecords<'r, T, R = Box<dyn Read + Send>> {
+    reader: &'r mut CsvReader<R>,
+    parser: CellParser,
+    _marker: PhantomData<T>,
+}
+
+impl<R: Read> CsvReader<R> {
+    /// Returns an iterator that deserializes each row into `T`.
+    ///
+    /// When the config has `has_headers` set, structs and maps match their fields with
+    /// the header names. Otherwise, or for tuples and sequences, cells are taken by position.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use process_csv::{Config, CsvReader};
+    /// # use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct User {
+    ///     #[serde(rename = "Name")]
+    ///     name: String,
+    ///     #[serde(rename = "Age")]
+    ///     age: u8,
+    /// }
+    ///
+    /// # let config = Config::build_from(["", "sample.csv"].map(String::from).into_iter()).unwrap();
+    /// let mut reader = CsvReader::build_from(config).unwrap();
+    ///
+    /// for user in reader.deserialize::<User>() {
+    ///     let user = user.unwrap();
+    ///     println!("{} is {}", user.name, user.age);
+    /// }
+    /// ```
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> DeserializeRecords<'_, T, R> {
+        DeserializeRecords {
+            parser: CellParser::from(self.dialect()),
+            reader: self,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned, R: Read> Iterator for DeserializeRecords<'_, T, R> {
+    type Item = Result<T, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.reader.next_row()? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let de = RecordDeserializer {
+            cells: row.iter().enumerate(),
+            headers: row.headers(),
+            parser: &self.parser,
+            pending: None,
+        };
+
+        Some(T::deserialize(de).map_err(|e| e.at(row.position())))
+    }
+}
+
+impl Record {
+    /// Deserializes the record into `T`, like `CsvReader::deserialize` does with the rows
+    /// it reads, e.g. on another thread than the reader's.
+    pub fn deserialize<T: DeserializeOwned>(&self, parser: &CellParser) -> Result<T, CsvError> {
+        let de = RecordDeserializer {
+            cells: self.iter().enumerate(),
+            headers: self.headers(),
+            parser,
+            pending: None,
+        };
+
+        T::deserialize(de).map_err(|e| match self.position() {
+            Some(position) => e.at(position),
+            None => e,
+        })
+    }
+}
+
+/// Deserializes a whole row, as a map when headers are known or as a sequence otherwise.
+struct RecordDeserializer<'a, I> {
+    cells: I,
+    headers: Option<&'a Headers>,
+    parser: &'a CellParser,
+    pending: Option<(usize, &'a [u8])>,
+}
+
+impl<'a, I> RecordDeserializer<'a, I>
+where
+    I: Iterator<Item = (usize, &'a [u8])>,
+{
+    fn cell<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        (i, raw): (usize, &'a [u8]),
+    ) -> Result<S::Value, CsvError> {
+        let header = self.headers.and_then(|h| h.get(i));
+
+        self.parser
+            .to_string(raw.to_vec())
+            .and_then(|value| seed.deserialize(CellDeserializer(value)))
+            .map_err(|e| e.in_cell(i, header, raw))
+    }
+}
+
+impl<'de, 'a, I> de::Deserializer<'de> for RecordDeserializer<'a, I>
+where
+    I: Iterator<Item = (usize, &'a [u8])>,
+{
+    type Error = CsvError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.headers {
+            Some(_) => visitor.visit_map(self),
+            None => visitor.visit_seq(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de, 'a, I> SeqAccess<'de> for RecordDeserializer<'a, I>
+where
+    I: Iterator<Item = (usize, &'a [u8])>,
+{
+    type Error = CsvError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        match self.cells.next() {
+            Some(cell) => self.cell(seed, cell).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'de, 'a, I> MapAccess<'de> for RecordDeserializer<'a, I>
+where
+    I: Iterator<Item = (usize, &'a [u8])>,
+{
+    type Error = CsvError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let headers = self.headers;
+
+        match self.cells.next() {
+            Some((i, raw)) if let Some(name) = headers.and_then(|h| h.get(i)) => {
+                self.pending = Some((i, raw));
+                let key: de::value::StrDeserializer<CsvError> = name.into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        let cell = self
+            .pending
+            .take()
+            .ok_or_else(|| CsvError::custom("value requested before its key"))?;
+        self.cell(seed, cell)
+    }
+}
+
+/// Deserializes a single unquoted cell, parsing it into the type the visitor asks for.
+struct CellDeserializer(String);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let value = self.0.trim().parse().map_err(CsvError::custom)?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for CellDeserializer {
+    type Error = CsvError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        #[serde(rename = "Name")]
+        name: String,
+        #[serde(rename = "Age")]
+        age: u8,
+        #[serde(rename = "Email")]
+        mail: Option<String>,
+    }
+
+    fn reader_from(content: &[u8]) -> CsvReader<&[u8]> {
+        CsvReader::from_reader(content, Dialect::default()).with_watermark(8)
+    }
+
+    #[test]
+    fn by_header_name() {
+        let mut reader = reader_from(b"Email,Age,Name\n,25,\"Johnson, Alice\"\n");
+
+        let users: Vec<User> = reader.deserialize().map(Result::unwrap).collect();
+
+        assert_eq!(
+            users,
+            vec![User {
+                name: "Johnson, Alice".to_string(),
+                age: 25,
+                mail: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn by_position() {
+        let mut reader = reader_from(b"Name,Age\nBob,30\n");
+
+        let rows: Vec<(String, u32)> = reader.deserialize().map(Result::unwrap).collect();
+
+        assert_eq!(rows, vec![("Bob".to_string(), 30)]);
+    }
+
+    #[test]
+    fn error_location() {
+        let mut reader = reader_from(b"Name,Age,Email\nBob,30,\nEve,3O,\n");
+        let mut users = reader.deserialize::<User>();
+
+        assert!(users.next().unwrap().is_ok());
+
+        let Some(Err(CsvError::Parse(e))) = users.next() else {
+            panic!("expected a parse error");
+        };
+        let position = e.position().unwrap();
+        assert_eq!((position.record, position.line, position.byte), (2, 3, 23));
+        assert_eq!(e.column(), Some(1));
+        assert_eq!(e.header(), Some("Age"));
+        assert_eq!(e.value(), Some("3O"));
+    }
+}
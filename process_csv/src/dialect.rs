@@ -0,0 +1,125 @@
+use std::env;
+
+use crate::{COMMA, CR, LF, QUOTES};
+
+/// Describes the shape of a CSV file: which bytes split cells and lines,
+/// how cells are quoted and what happens to whitespace around them.
+///
+/// The default dialect follows RFC 4180: comma separated, double quoted
+/// cells with doubled quotes (`""`) as escape and `\r\n` line endings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Byte that makes the next byte inside a quoted cell literal (e.g. `\"`).
+    /// When `None`, quotes are escaped by doubling them.
+    pub escape: Option<u8>,
+    pub terminator: Terminator,
+    pub trim: Trim,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    /// `\r\n`, `\n` and `\r` all end a line, `\r\n` counting as a single terminator.
+    CRLF,
+    /// Only the given byte ends a line.
+    Any(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trim {
+    None,
+    /// Removes ASCII whitespace around the cell. Whitespace inside quotes is kept.
+    Whitespace,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: COMMA,
+            quote: QUOTES,
+            escape: None,
+            terminator: Terminator::CRLF,
+            trim: Trim::None,
+        }
+    }
+}
+
+impl Dialect {
+    /// Builds a dialect from the `DELIMITER`, `QUOTE`, `ESCAPE`, `TERMINATOR` and `TRIM`
+    /// environment variables, using the default for any of them that is not set.
+    ///
+    /// Single characters are taken as is, `\t` or `tab` stand for a tab. `TERMINATOR`
+    /// also accepts `CRLF`, and `TRIM` accepts `none` or `whitespace`.
+    pub fn from_env() -> Result<Dialect, &'static str> {
+        let mut dialect = Dialect::default();
+
+        if let Ok(val) = env::var("DELIMITER") {
+            dialect.delimiter = parse_byte(&val).ok_or("Failed to parse 'DELIMITER'")?;
+        }
+        if let Ok(val) = env::var("QUOTE") {
+            dialect.quote = parse_byte(&val).ok_or("Failed to parse 'QUOTE'")?;
+        }
+        if let Ok(val) = env::var("ESCAPE") {
+            dialect.escape = Some(parse_byte(&val).ok_or("Failed to parse 'ESCAPE'")?);
+        }
+        if let Ok(val) = env::var("TERMINATOR") {
+            dialect.terminator = match val.as_str() {
+                "CRLF" | "crlf" => Terminator::CRLF,
+                _ => Terminator::Any(parse_byte(&val).ok_or("Failed to parse 'TERMINATOR'")?),
+            };
+        }
+        if let Ok(val) = env::var("TRIM") {
+            dialect.trim = match val.as_str() {
+                "none" => Trim::None,
+                "whitespace" => Trim::Whitespace,
+                _ => return Err("Failed to parse 'TRIM'"),
+            };
+        }
+
+        Ok(dialect)
+    }
+
+    pub fn is_terminator(&self, byte: u8) -> bool {
+        match self.terminator {
+            Terminator::CRLF => byte == LF || byte == CR,
+            Terminator::Any(b) => byte == b,
+        }
+    }
+}
+
+fn parse_byte(val: &str) -> Option<u8> {
+    match val {
+        "\\t" | "tab" => Some(b'\t'),
+        _ if val.len() == 1 => Some(val.as_bytes()[0]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_single_bytes() {
+        assert_eq!(parse_byte(";"), Some(b';'));
+        assert_eq!(parse_byte("tab"), Some(b'\t'));
+        assert_eq!(parse_byte("\\t"), Some(b'\t'));
+        assert_eq!(parse_byte(""), None);
+        assert_eq!(parse_byte(";;"), None);
+    }
+
+    #[test]
+    fn crlf_terminator() {
+        let dialect = Dialect::default();
+        assert!(dialect.is_terminator(b'\n'));
+        assert!(dialect.is_terminator(b'\r'));
+        assert!(!dialect.is_terminator(b','));
+
+        let dialect = Dialect {
+            terminator: Terminator::Any(b'\n'),
+            ..Dialect::default()
+        };
+        assert!(!dialect.is_terminator(b'\r'));
+    }
+}
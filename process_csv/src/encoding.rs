@@ -0,0 +1,233 @@
+use std::io::{self, Cursor, Read};
+
+pub use encoding_rs::Encoding;
+use encoding_rs::{CoderResult, Decoder, DecoderResult, UTF_8};
+
+use crate::compression::read_prefix;
+
+const DECODED_LEN: usize = 1024 * 8; // 8KB
+
+/// Input ready to be split, see `decode`.
+pub struct Decoded {
+    pub input: Box<dyn Read + Send>,
+    /// Length of the byte order mark removed from the start of the input.
+    pub bom: usize,
+    /// Whether the input is transcoded, in which case byte positions are counted in the
+    /// UTF-8 text rather than in the original input.
+    pub transcoded: bool,
+}
+
+/// Strips the byte order mark of `input` and transcodes it to UTF-8 when needed.
+///
+/// A UTF-8, UTF-16 LE or UTF-16 BE byte order mark takes precedence over `encoding`.
+/// UTF-8 input is left as it is, unless `lossy` is set, so invalid cells are reported
+/// by `CellParser::to_string` with their position.
+///
+/// # Parameters
+/// - `encoding`: The encoding of the input when it has no byte order mark, e.g.
+///   `Encoding::for_label(b"latin1")`.
+/// - `lossy`: Whether invalid sequences are replaced with `U+FFFD` rather than reported
+///   as an `io::ErrorKind::InvalidData` error.
+pub fn decode<R>(mut input: R, encoding: &'static Encoding, lossy: bool) -> io::Result<Decoded>
+where
+    R: Read + Send + 'static,
+{
+    let mut prefix = [0; 3];
+    let n = read_prefix(&mut input, &mut prefix)?;
+
+    let (encoding, bom) = Encoding::for_bom(&prefix[..n]).unwrap_or((encoding, 0));
+    let mut prefix = Cursor::new(prefix);
+    prefix.set_position(bom as u64);
+    let input = prefix.take((n - bom) as u64).chain(input);
+
+    if encoding == UTF_8 && !lossy {
+        return Ok(Decoded {
+            input: Box::new(input),
+            bom,
+            transcoded: false,
+        });
+    }
+
+    Ok(Decoded {
+        input: Box::new(Transcoder::new(input, encoding, lossy)),
+        bom,
+        transcoded: true,
+    })
+}
+
+/// Reads `input` in `encoding` as UTF-8 text.
+pub struct Transcoder<R> {
+    input: R,
+    encoding: &'static Encoding,
+    decoder: Decoder,
+    lossy: bool,
+    raw: Vec<u8>,
+    raw_start: usize,
+    raw_end: usize,
+    decoded: Vec<u8>,
+    decoded_start: usize,
+    consumed: u64, // bytes of the input decoded so far
+    eof: bool,
+    done: bool,
+}
+
+impl<R: Read> Transcoder<R> {
+    /// A byte order mark at the start of `input` is decoded like any other character,
+    /// use `decode` to have it detected and removed.
+    pub fn new(input: R, encoding: &'static Encoding, lossy: bool) -> Self {
+        Transcoder {
+            input,
+            encoding,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            lossy,
+            raw: vec![0; 1024 * 8], // 8KB
+            raw_start: 0,
+            raw_end: 0,
+            decoded: Vec::new(),
+            decoded_start: 0,
+            consumed: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Decodes the next bytes of the input into `decoded`, reading more when needed.
+    ///
+    /// # Returns
+    /// - `Ok(false)` once the whole input was decoded.
+    fn decode_more(&mut self) -> io::Result<bool> {
+        if self.done {
+            return Ok(false);
+        }
+        if self.raw_start == self.raw_end && !self.eof {
+            self.raw_end = self.input.read(&mut self.raw)?;
+            self.raw_start = 0;
+            self.eof = self.raw_end == 0;
+        }
+
+        self.decoded.resize(DECODED_LEN, 0);
+        let src = &self.raw[self.raw_start..self.raw_end];
+        let dst = &mut self.decoded;
+        let last = self.eof;
+
+        let (input_empty, read, written) = if self.lossy {
+            let (result, read, written, _) = self.decoder.decode_to_utf8(src, dst, last);
+            (result == CoderResult::InputEmpty, read, written)
+        } else {
+            match self
+                .decoder
+                .decode_to_utf8_without_replacement(src, dst, last)
+            {
+                (DecoderResult::Malformed(len, after), read, _) => {
+                    let end = self.consumed + read as u64 - after as u64;
+                    let message = format!(
+                        "Invalid {} sequence at byte {}",
+                        self.encoding.name(),
+                        end - len as u64
+                    );
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+                (result, read, written) => (result == DecoderResult::InputEmpty, read, written),
+            }
+        };
+
+        self.raw_start += read;
+        self.consumed += read as u64;
+        self.decoded.truncate(written);
+        self.decoded_start = 0;
+        self.done = last && input_empty;
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for Transcoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded_start == self.decoded.len() {
+            if !self.decode_more()? {
+                return Ok(0);
+            }
+        }
+
+        let decoded = &self.decoded[self.decoded_start..];
+        let n = decoded.len().min(buf.len());
+        buf[..n].copy_from_slice(&decoded[..n]);
+        self.decoded_start += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use encoding_rs::{UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+    /// Reads the decoded input one byte at a time.
+    fn read(
+        input: &[u8],
+        encoding: &'static Encoding,
+        lossy: bool,
+    ) -> io::Result<(Vec<u8>, usize)> {
+        let mut decoded = decode(Cursor::new(input.to_vec()), encoding, lossy)?;
+        let mut text = Vec::new();
+        let mut byte = [0];
+        while decoded.input.read(&mut byte)? == 1 {
+            text.push(byte[0]);
+        }
+        Ok((text, decoded.bom))
+    }
+
+    #[test]
+    fn single_byte_encodings() {
+        let latin1 = Encoding::for_label(b"latin1").unwrap();
+        let (text, _) = read(b"caf\xe9,\x80\n", latin1, false).unwrap();
+        assert_eq!(text, "café,€\n".as_bytes());
+        assert_eq!(
+            read(b"a;b", WINDOWS_1252, false).unwrap(),
+            (b"a;b".to_vec(), 0)
+        );
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        assert_eq!(
+            read(b"\xef\xbb\xbfa,b", UTF_8, false).unwrap(),
+            (b"a,b".to_vec(), 3)
+        );
+        assert_eq!(
+            read(b"\xef\xbb", UTF_8, false).unwrap(),
+            (b"\xef\xbb".to_vec(), 0)
+        );
+
+        let (text, bom) = read(b"\xff\xfea\0,\0\xe9\0", WINDOWS_1252, false).unwrap();
+        assert_eq!((text, bom), ("a,é".as_bytes().to_vec(), 2));
+        assert_eq!(
+            read(b"\xfe\xff\0a\0\n", UTF_8, false).unwrap(),
+            (b"a\n".to_vec(), 2)
+        );
+        assert_eq!(
+            read(b"a\0b\0", UTF_16LE, false).unwrap(),
+            (b"ab".to_vec(), 0)
+        );
+    }
+
+    #[test]
+    fn invalid_sequences() {
+        // UTF-8 isn't checked here, the cells are when they are converted.
+        assert_eq!(read(b"a\xff", UTF_8, false).unwrap().0, b"a\xff");
+
+        let e = read(b"a\0\0\xd8b\0", UTF_16LE, false).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "Invalid UTF-16LE sequence at byte 2");
+
+        assert_eq!(
+            read(b"a\xffb", UTF_8, true).unwrap().0,
+            "a\u{fffd}b".as_bytes()
+        );
+        assert_eq!(
+            read(b"\0a\xd8\0", UTF_16BE, true).unwrap().0,
+            "a\u{fffd}".as_bytes()
+        );
+    }
+}
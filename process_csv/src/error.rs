@@ -0,0 +1,320 @@
+use std::{error::Error, fmt, io, str::Utf8Error};
+
+use serde::de;
+
+use crate::Anomaly;
+use crate::ser::SerializeError;
+
+/// Where a record starts in the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    /// 0-based index of the record, the header row included.
+    pub record: u64,
+    /// 1-based line the record starts on. Line breaks inside quoted cells are counted.
+    pub line: u64,
+    /// 0-based offset of the first byte of the record.
+    pub byte: u64,
+}
+
+impl Position {
+    /// Moves a position counted from the start of a range of the input, as record 0 on
+    /// line 1, to the record and line the range starts at.
+    pub(crate) fn rebase(self, base: Position) -> Position {
+        Position {
+            record: base.record + self.record,
+            line: base.line + self.line - 1,
+            byte: self.byte,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "record {}, line {}, byte {}",
+            self.record, self.line, self.byte
+        )
+    }
+}
+
+/// A cell that couldn't be converted into the value asked for.
+#[derive(Debug)]
+pub struct ParseError {
+    position: Option<Position>,
+    column: Option<usize>,
+    header: Option<String>,
+    value: Option<String>,
+    message: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(message: impl fmt::Display, value: Option<&str>) -> Self {
+        ParseError {
+            position: None,
+            column: None,
+            header: None,
+            value: value.map(String::from),
+            message: message.to_string(),
+        }
+    }
+
+    /// Fills in the cell the error comes from.
+    pub(crate) fn located(
+        mut self,
+        position: Position,
+        column: usize,
+        header: Option<&str>,
+    ) -> Self {
+        self.position = Some(position);
+        self.column = Some(column);
+        self.header = header.map(String::from);
+        self
+    }
+
+    /// Fills in the row the error comes from, for errors about the whole row.
+    pub(crate) fn at_row(mut self, position: Position) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+
+    /// 0-based index of the cell in the record.
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
+
+    pub fn header(&self) -> Option<&str> {
+        self.header.as_deref()
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_location(f, self.position, self.column)?;
+        if let Some(header) = &self.header {
+            write!(f, " ({header:?})")?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, ", value {value:?}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Errors produced while reading a CSV file.
+///
+/// Errors raised by `CellParser` don't know where the cell comes from, their position
+/// and column are filled in by the callers that do, such as `CsvReader::deserialize`.
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    Utf8 {
+        position: Option<Position>,
+        column: Option<usize>,
+        source: Utf8Error,
+    },
+    /// The file ends inside a quoted cell.
+    UnterminatedQuote {
+        position: Position,
+    },
+    /// A row doesn't have as many cells as the header, or as the first row of a `Joiner`
+    /// input without one.
+    FieldCountMismatch {
+        position: Position,
+        expected: usize,
+        found: usize,
+    },
+    Parse(ParseError),
+    Serialize(SerializeError),
+    /// `has_headers` is set but the file has no rows.
+    NoHeaders,
+    /// A header cell is blank.
+    MissingHeader {
+        column: usize,
+    },
+    DuplicateHeader {
+        name: String,
+        column: usize,
+    },
+    /// A column given by name isn't in the header.
+    UnknownColumn(String),
+    /// Validation rules that can't be loaded, see `Validator`.
+    Rules(String),
+    /// A filter that can't be parsed, see `Expr::parse`.
+    Expression(String),
+    /// Arguments a function can't work with, e.g. an aggregation without its column.
+    InvalidArgument(String),
+    /// A row comes before the previous one in an input expected to be sorted, see
+    /// `Joiner::sorted`.
+    Unsorted {
+        position: Position,
+    },
+    /// A row holding a malformed quote, with the `Recovery::Strict` policy.
+    Malformed(Anomaly),
+}
+
+impl CsvError {
+    /// Position of the record the error comes from, when known.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            CsvError::Utf8 { position, .. } => *position,
+            CsvError::UnterminatedQuote { position } => Some(*position),
+            CsvError::FieldCountMismatch { position, .. } => Some(*position),
+            CsvError::Unsorted { position } => Some(*position),
+            CsvError::Malformed(anomaly) => Some(anomaly.position),
+            CsvError::Parse(e) => e.position,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn rebase(mut self, base: Position) -> Self {
+        match &mut self {
+            CsvError::Utf8 {
+                position: Some(position),
+                ..
+            }
+            | CsvError::UnterminatedQuote { position }
+            | CsvError::FieldCountMismatch { position, .. }
+            | CsvError::Unsorted { position } => *position = position.rebase(base),
+            CsvError::Parse(e) => e.position = e.position.map(|p| p.rebase(base)),
+            CsvError::Malformed(anomaly) => *anomaly = anomaly.rebase(base),
+            _ => {}
+        }
+        self
+    }
+
+    /// Fills in the position of the record the error comes from, when it was missing.
+    pub(crate) fn at(mut self, at: Position) -> Self {
+        match &mut self {
+            CsvError::Utf8 { position, .. } => {
+                position.get_or_insert(at);
+            }
+            CsvError::Parse(e) => {
+                e.position.get_or_insert(at);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Fills in the cell the error comes from, when it was missing.
+    pub(crate) fn in_cell(mut self, i: usize, header: Option<&str>, raw: &[u8]) -> Self {
+        match &mut self {
+            CsvError::Utf8 { column, .. } => {
+                column.get_or_insert(i);
+            }
+            CsvError::Parse(e) if e.column.is_none() => {
+                e.column = Some(i);
+                e.header = header.map(String::from);
+                e.value
+                    .get_or_insert_with(|| String::from_utf8_lossy(raw).into_owned());
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+fn write_location(
+    f: &mut fmt::Formatter<'_>,
+    position: Option<Position>,
+    column: Option<usize>,
+) -> fmt::Result {
+    match (position, column) {
+        (Some(position), Some(column)) => write!(f, "at {position}, column {column}"),
+        (Some(position), None) => write!(f, "at {position}"),
+        (None, Some(column)) => write!(f, "at column {column}"),
+        (None, None) => write!(f, "at unknown position"),
+    }
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Io(e) => write!(f, "I/O error: {e}"),
+            CsvError::Utf8 {
+                position,
+                column,
+                source,
+            } => {
+                write!(f, "Invalid UTF-8 ")?;
+                write_location(f, *position, *column)?;
+                write!(f, ": {source}")
+            }
+            CsvError::UnterminatedQuote { position } => {
+                write!(
+                    f,
+                    "Quoted cell is never closed, record starts at {position}"
+                )
+            }
+            CsvError::FieldCountMismatch {
+                position,
+                expected,
+                found,
+            } => {
+                write!(f, "Expected {expected} cells, found {found} at {position}")
+            }
+            CsvError::Parse(e) => write!(f, "Parse error {e}"),
+            CsvError::Serialize(e) => write!(f, "Serialize error: {e}"),
+            CsvError::NoHeaders => write!(f, "Expected a header row, the file is empty"),
+            CsvError::MissingHeader { column } => write!(f, "Header of column {column} is blank"),
+            CsvError::DuplicateHeader { name, column } => {
+                write!(f, "Header {name:?} of column {column} is repeated")
+            }
+            CsvError::UnknownColumn(name) => write!(f, "No column named {name:?}"),
+            CsvError::Rules(message) => write!(f, "Invalid rules: {message}"),
+            CsvError::Expression(message) => write!(f, "Invalid expression: {message}"),
+            CsvError::InvalidArgument(message) => write!(f, "Invalid argument: {message}"),
+            CsvError::Unsorted { position } => {
+                write!(f, "Rows aren't sorted by the join keys at {position}")
+            }
+            CsvError::Malformed(anomaly) => write!(f, "Malformed row: {anomaly}"),
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvError::Io(e) => Some(e),
+            CsvError::Utf8 { source, .. } => Some(source),
+            CsvError::Parse(e) => Some(e),
+            CsvError::Serialize(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl de::Error for CsvError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        CsvError::Parse(ParseError::new(msg, None))
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(value: io::Error) -> Self {
+        CsvError::Io(value)
+    }
+}
+
+impl From<ParseError> for CsvError {
+    fn from(value: ParseError) -> Self {
+        CsvError::Parse(value)
+    }
+}
+
+impl From<SerializeError> for CsvError {
+    fn from(value: SerializeError) -> Self {
+        CsvError::Serialize(value)
+    }
+}
@@ -0,0 +1,440 @@
+use std::cmp::Ordering;
+
+use crate::headers::column_of;
+use crate::{BorrowedRecord, CellParser, CsvError, Headers};
+
+/// A condition on the cells of a row, such as `Age > 30 && Country == "USA"`.
+///
+/// Comparisons take a column or a literal on each side, with `==`, `!=`, `<`, `<=`, `>`
+/// or `>=`. Values are compared as numbers when both sides are numbers, and as strings
+/// otherwise. Conditions combine with `&&`, `||`, `!` and parentheses.
+///
+/// Columns are bare words like `Age`, or quoted with backticks for the other names and
+/// for 0-based indexes, like `` `First Name` `` or `` `2` ``. Strings are quoted with
+/// double or single quotes. Numbers start with a digit, `-` or `.`, so words such as
+/// `inf` or `NaN` are columns.
+///
+/// # Example
+/// ```
+/// use process_csv::{CsvReader, Dialect, Expr};
+///
+/// let expr = Expr::parse("Age > 30 && (Country == 'USA' || !(`Name` != \"Bob\"))").unwrap();
+///
+/// let mut reader = CsvReader::from_reader(&b"Name,Age,Country\nBob,31,UK\n"[..], Dialect::default());
+/// let filter = expr.bind(reader.headers().unwrap()).unwrap();
+///
+/// let row = reader.next_row().unwrap().unwrap();
+/// assert!(filter.matches(&row).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Compare(Operand, Op, Operand),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// A column by name, as written in the expression.
+    Name(String),
+    /// A column by 0-based index, once bound to the header.
+    Column(usize),
+    Literal(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+impl Expr {
+    /// # Returns
+    /// - `Err(CsvError::Expression)` with the byte offset the expression stops making sense.
+    pub fn parse(text: &str) -> Result<Expr, CsvError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            end: text.len(),
+        };
+        let expr = parser.or()?;
+
+        match parser.tokens.get(parser.pos) {
+            Some((offset, _)) => Err(invalid("Unexpected token", *offset)),
+            None => Ok(expr),
+        }
+    }
+
+    /// Resolves the column names against the header of the file the rows come from.
+    pub fn bind(&self, headers: Option<&Headers>) -> Result<Filter, CsvError> {
+        Ok(Filter {
+            expr: self.bound(headers)?,
+            parser: CellParser::default(),
+        })
+    }
+
+    fn bound(&self, headers: Option<&Headers>) -> Result<Expr, CsvError> {
+        let bind = |operand: &Operand| match operand {
+            Operand::Name(name) => column_of(headers, name).map(Operand::Column),
+            operand => Ok(operand.clone()),
+        };
+
+        Ok(match self {
+            Expr::Compare(left, op, right) => Expr::Compare(bind(left)?, *op, bind(right)?),
+            Expr::And(a, b) => Expr::And(Box::new(a.bound(headers)?), Box::new(b.bound(headers)?)),
+            Expr::Or(a, b) => Expr::Or(Box::new(a.bound(headers)?), Box::new(b.bound(headers)?)),
+            Expr::Not(a) => Expr::Not(Box::new(a.bound(headers)?)),
+        })
+    }
+}
+
+/// An `Expr` bound to the columns of a file, see `Expr::bind`.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+    parser: CellParser,
+}
+
+impl Filter {
+    /// Sets the parser unquoting the cells, built from the dialect of the reader.
+    pub fn with_parser(mut self, parser: CellParser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Evaluates the expression on a row. Missing cells are empty.
+    pub fn matches(&self, row: &BorrowedRecord) -> Result<bool, CsvError> {
+        self.eval(&self.expr, row)
+    }
+
+    fn eval(&self, expr: &Expr, row: &BorrowedRecord) -> Result<bool, CsvError> {
+        Ok(match expr {
+            Expr::Compare(left, op, right) => {
+                let (left, right) = (self.value(left, row)?, self.value(right, row)?);
+                let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+                    (Ok(a), Ok(b)) => a.total_cmp(&b),
+                    _ => left.cmp(&right),
+                };
+                op.holds(ordering)
+            }
+            Expr::And(a, b) => self.eval(a, row)? && self.eval(b, row)?,
+            Expr::Or(a, b) => self.eval(a, row)? || self.eval(b, row)?,
+            Expr::Not(a) => !self.eval(a, row)?,
+        })
+    }
+
+    fn value(&self, operand: &Operand, row: &BorrowedRecord) -> Result<String, CsvError> {
+        match operand {
+            Operand::Column(i) => {
+                let raw = row.get(*i).unwrap_or_default();
+                self.parser
+                    .to_string(raw.to_vec())
+                    .map_err(|e| e.at(row.position()).in_cell(*i, None, raw))
+            }
+            Operand::Literal(value) => Ok(value.clone()),
+            Operand::Name(name) => unreachable!("column {name:?} isn't bound"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Operand(Operand),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn invalid(message: &str, offset: usize) -> CsvError {
+    CsvError::Expression(format!("{message} at byte {offset}"))
+}
+
+/// Splits the expression into tokens, each with its byte offset.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, CsvError> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let two = &bytes[i..bytes.len().min(i + 2)];
+
+        let token = match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            _ if two == b"&&" => Token::And,
+            _ if two == b"||" => Token::Or,
+            _ if two == b"==" => Token::Op(Op::Eq),
+            _ if two == b"!=" => Token::Op(Op::Ne),
+            _ if two == b"<=" => Token::Op(Op::Le),
+            _ if two == b">=" => Token::Op(Op::Ge),
+            b'<' => Token::Op(Op::Lt),
+            b'>' => Token::Op(Op::Gt),
+            b'!' => Token::Not,
+            b'(' => Token::Open,
+            b')' => Token::Close,
+            quote @ (b'"' | b'\'' | b'`') => {
+                let len = text[i + 1..]
+                    .find(quote as char)
+                    .ok_or_else(|| invalid("Unclosed quote", start))?;
+                let value = text[i + 1..i + 1 + len].to_string();
+                i += len + 2;
+
+                let operand = match quote {
+                    b'`' => Operand::Name(value),
+                    _ => Operand::Literal(value),
+                };
+                tokens.push((start, Token::Operand(operand)));
+                continue;
+            }
+            b if b.is_ascii_alphanumeric() || b == b'_' || b == b'-' || b == b'.' => {
+                let len = bytes[i..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_alphanumeric() || b"_-.".contains(b))
+                    .count();
+                let word = text[i..i + len].to_string();
+                i += len;
+
+                let numeric = b.is_ascii_digit() || b == b'-' || b == b'.';
+                let operand = match word.parse::<f64>() {
+                    Ok(_) if numeric => Operand::Literal(word),
+                    _ => Operand::Name(word),
+                };
+                tokens.push((start, Token::Operand(operand)));
+                continue;
+            }
+            _ => return Err(invalid("Unexpected character", start)),
+        };
+
+        i += match token {
+            Token::Op(Op::Lt | Op::Gt) | Token::Not | Token::Open | Token::Close => 1,
+            _ => 2,
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, `||` binding looser than `&&`, itself looser
+/// than `!`.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    /// Offset of the next token, or the end of the expression.
+    fn offset(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some((offset, _)) => *offset,
+            None => self.end,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, CsvError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, CsvError> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CsvError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                let offset = self.offset();
+                match self.next() {
+                    Some((_, Token::Close)) => Ok(expr),
+                    _ => Err(invalid("Expected ')'", offset)),
+                }
+            }
+            _ => {
+                let left = self.operand()?;
+                let offset = self.offset();
+                let Some((_, Token::Op(op))) = self.next() else {
+                    return Err(invalid("Expected a comparison", offset));
+                };
+                Ok(Expr::Compare(left, op, self.operand()?))
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, CsvError> {
+        let offset = self.offset();
+        match self.next() {
+            Some((_, Token::Operand(operand))) => Ok(operand),
+            _ => Err(invalid("Expected a column or a value", offset)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CsvReader, Dialect};
+
+    const USERS: &[u8] = b"Name,Age,Country,First Name\n\
+        Smith,25,USA,Alice\n\
+        Jones,31,UK,Bob\n\
+        \"Brown\",40,\"USA\",Carol\n\
+        Lee,9,USA,Dave\n";
+
+    fn matching(expr: &str) -> Vec<String> {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let filter = Expr::parse(expr)
+            .unwrap()
+            .bind(reader.headers().unwrap())
+            .unwrap();
+
+        let mut names = Vec::new();
+        while let Some(row) = reader.next_row() {
+            let row = row.unwrap();
+            if filter.matches(&row).unwrap() {
+                names.push(String::from_utf8(row.get(3).unwrap().to_vec()).unwrap());
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(matching("Age > 30 && Country == \"USA\""), vec!["Carol"]);
+        assert_eq!(
+            matching("Age >= 31 || `First Name` == 'Alice'"),
+            vec!["Alice", "Bob", "Carol"]
+        );
+        assert_eq!(
+            matching("!(Country == 'USA') || Age < 10"),
+            vec!["Bob", "Dave"]
+        );
+        assert_eq!(matching("Age <= 25.0 && `0` != 'Lee'"), vec!["Alice"]);
+        // "9" < "25" as numbers, but not as strings.
+        assert_eq!(matching("Age < 25"), vec!["Dave"]);
+        assert_eq!(matching("Name < 'Jones'"), vec!["Carol"]);
+    }
+
+    #[test]
+    fn precedence() {
+        let expr = Expr::parse("a == 1 || b == 2 && !c == 3").unwrap();
+        let compare = |name: &str, value: &str| {
+            Box::new(Expr::Compare(
+                Operand::Name(name.to_string()),
+                Op::Eq,
+                Operand::Literal(value.to_string()),
+            ))
+        };
+        assert_eq!(
+            expr,
+            Expr::Or(
+                compare("a", "1"),
+                Box::new(Expr::And(
+                    compare("b", "2"),
+                    Box::new(Expr::Not(compare("c", "3")))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn number_like_names() {
+        let content = b"inf,nan,NaN\n1,2,3\n";
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+        let headers = reader.headers().unwrap();
+        let filter = |text: &str| Expr::parse(text).unwrap().bind(headers).unwrap();
+
+        let (inf, nan) = (
+            filter("inf > 5"),
+            filter("nan == 2 && NaN < 4 && -inf < .5"),
+        );
+        let row = reader.next_row().unwrap().unwrap();
+        assert!(!inf.matches(&row).unwrap());
+        assert!(nan.matches(&row).unwrap());
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let error = |text: &str| Expr::parse(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("Age >"),
+            "Invalid expression: Expected a column or a value at byte 5"
+        );
+        assert_eq!(
+            error("Age 30"),
+            "Invalid expression: Expected a comparison at byte 4"
+        );
+        assert_eq!(
+            error("(Age > 30"),
+            "Invalid expression: Expected ')' at byte 9"
+        );
+        assert_eq!(
+            error("Age > 30)"),
+            "Invalid expression: Unexpected token at byte 8"
+        );
+        assert_eq!(
+            error("Name == 'Bob"),
+            "Invalid expression: Unclosed quote at byte 8"
+        );
+        assert_eq!(
+            error("Age = 30"),
+            "Invalid expression: Unexpected character at byte 4"
+        );
+
+        let unknown = Expr::parse("City == 'Paris'")
+            .unwrap()
+            .bind(None)
+            .unwrap_err();
+        assert_eq!(unknown.to_string(), "No column named \"City\"");
+    }
+}
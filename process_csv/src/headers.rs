@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::{BorrowedRecord, CellParser, CsvError};
+
+/// Column names taken from the first row of a file, see `CsvReader::headers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Headers {
+    names: Vec<String>,
+    positions: HashMap<String, usize>,
+}
+
+impl Headers {
+    /// Parses the header row, rejecting blank and repeated names.
+    pub(crate) fn build_from(row: BorrowedRecord, parser: &CellParser) -> Result<Self, CsvError> {
+        let mut names = Vec::with_capacity(row.len());
+        let mut positions = HashMap::with_capacity(row.len());
+
+        for (column, cell) in row.iter().enumerate() {
+            let name = parser
+                .to_string(cell.to_vec())
+                .map_err(|e| e.at(row.position()).in_cell(column, None, cell))?;
+
+            if name.trim().is_empty() {
+                return Err(CsvError::MissingHeader { column });
+            }
+            if positions.insert(name.clone(), column).is_some() {
+                return Err(CsvError::DuplicateHeader { name, column });
+            }
+            names.push(name);
+        }
+
+        Ok(Headers { names, positions })
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.names.get(i).map(|n| n.as_str())
+    }
+
+    /// Returns the position of the column called `name`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.positions.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|n| n.as_str())
+    }
+}
+
+/// Finds the column called `name` in the header, or taken as a 0-based index when the
+/// header has no such column, e.g. `2` for the third column of a file without headers.
+pub(crate) fn column_of(headers: Option<&Headers>, name: &str) -> Result<usize, CsvError> {
+    headers
+        .and_then(|headers| headers.position(name))
+        .or_else(|| name.parse().ok())
+        .ok_or_else(|| CsvError::UnknownColumn(name.to_string()))
+}
@@ -1,61 +1,386 @@
-use std::{error::Error, str::FromStr};
+use std::{fmt::Display, str::FromStr};
 
-use crate::{CR, QUOTES};
+use chrono::{NaiveDate, NaiveDateTime};
 
-enum Trim {
-    Quotes = 1,
-    QuotesAndCR = 2,
+use crate::{CsvError, Dialect, ParseError, Trim};
+
+/// Turns the raw byte cells yielded by `CsvReader` into values, following the
+/// quoting, escaping and trimming rules of a `Dialect`.
+///
+/// The tokens read as booleans or nulls and the decimal separator of floats are
+/// configurable, by default:
+/// - `true`, `yes`, `y` and `1` are true, `false`, `no`, `n` and `0` are false, in any case.
+/// - An empty cell, `NA` and `NULL` are nulls.
+/// - `.` separates decimals and there's no thousands separator.
+#[derive(Debug, Clone)]
+pub struct CellParser {
+    dialect: Dialect,
+    truthy: Vec<String>,
+    falsy: Vec<String>,
+    nulls: Vec<String>,
+    decimal: u8,
+    thousands: Option<u8>,
+}
+impl Default for CellParser {
+    fn default() -> Self {
+        CellParser::from(Dialect::default())
+    }
+}
+impl From<Dialect> for CellParser {
+    fn from(dialect: Dialect) -> Self {
+        let tokens = |tokens: &[&str]| tokens.iter().map(|t| t.to_string()).collect();
+
+        CellParser {
+            dialect,
+            truthy: tokens(&["true", "yes", "y", "1"]),
+            falsy: tokens(&["false", "no", "n", "0"]),
+            nulls: tokens(&["", "NA", "NULL"]),
+            decimal: b'.',
+            thousands: None,
+        }
+    }
 }
 
-pub struct CellParser {}
 impl CellParser {
-    #[rustfmt::skip]
-    pub fn to_string(mut cell: Vec<u8>) -> Result<String, Box<dyn Error>> {
-        match (cell.first(), cell.last()) {
-            (Some(&QUOTES), Some(&CR)) => Self::normalize(&mut cell, Trim::QuotesAndCR),
-            (Some(&QUOTES), _)         => Self::normalize(&mut cell, Trim::Quotes),
-            (_, Some(&CR))             => { cell.pop(); }
-            _                          => {}
+    /// Sets the tokens `to_bool` reads as true and as false, compared ignoring ASCII case.
+    pub fn with_bool_tokens(mut self, truthy: &[&str], falsy: &[&str]) -> Self {
+        self.truthy = truthy.iter().map(|t| t.to_string()).collect();
+        self.falsy = falsy.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    /// Sets the tokens `to_option` reads as `None`, compared as they are.
+    pub fn with_null_tokens(mut self, nulls: &[&str]) -> Self {
+        self.nulls = nulls.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    /// Sets the separators of `to_float`, e.g. `b','` and `Some(b'.')` for `1.234,5`.
+    pub fn with_decimal_separator(mut self, decimal: u8, thousands: Option<u8>) -> Self {
+        self.decimal = decimal;
+        self.thousands = thousands;
+        self
+    }
+
+    pub fn to_string(&self, mut cell: Vec<u8>) -> Result<String, CsvError> {
+        if self.dialect.trim == Trim::Whitespace {
+            Self::trim(&mut cell);
+        }
+        if cell.first() == Some(&self.dialect.quote) && self.is_closed(&cell) {
+            self.normalize(&mut cell);
         }
 
-        String::from_utf8(cell).map_err(|e| e.into())
+        String::from_utf8(cell).map_err(|e| CsvError::Utf8 {
+            position: None,
+            column: None,
+            source: e.utf8_error(),
+        })
     }
 
-    pub fn to_int<T>(cell: Vec<u8>) -> Result<T, Box<dyn Error>>
+    /// Parses the cell into any `FromStr` type, as `str::parse` does.
+    pub fn parse<T>(&self, cell: Vec<u8>) -> Result<T, CsvError>
     where
         T: FromStr,
-        T::Err: Error + 'static,
+        T::Err: Display,
     {
-        let cell = String::from_utf8(cell).map_err(|e| -> Box<dyn Error> { Box::new(e) })?;
+        let cell = self.to_string(cell)?;
         cell.parse::<T>()
-            .map_err(|e| -> Box<dyn Error> { Box::new(e) })
+            .map_err(|e| ParseError::new(e, Some(&cell)).into())
+    }
+
+    #[deprecated(note = "renamed to `parse`, it isn't limited to integers")]
+    pub fn to_int<T>(&self, cell: Vec<u8>) -> Result<T, CsvError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.parse(cell)
+    }
+
+    /// Parses a float written with the decimal and thousands separators of the parser.
+    pub fn to_float(&self, cell: Vec<u8>) -> Result<f64, CsvError> {
+        let cell = self.to_string(cell)?;
+
+        let float: String = cell
+            .chars()
+            .filter(|&c| Some(c) != self.thousands.map(char::from))
+            .map(|c| match c {
+                c if c == char::from(self.decimal) => '.',
+                '.' => ',', // not a separator here, so invalid
+                c => c,
+            })
+            .collect();
+
+        float
+            .parse()
+            .map_err(|e| ParseError::new(e, Some(&cell)).into())
+    }
+
+    pub fn to_bool(&self, cell: Vec<u8>) -> Result<bool, CsvError> {
+        let cell = self.to_string(cell)?;
+        let matches = |tokens: &[String]| tokens.iter().any(|t| t.eq_ignore_ascii_case(&cell));
+
+        if matches(&self.truthy) {
+            Ok(true)
+        } else if matches(&self.falsy) {
+            Ok(false)
+        } else {
+            let message = format!("expected one of {:?} or {:?}", self.truthy, self.falsy);
+            Err(ParseError::new(message, Some(&cell)).into())
+        }
+    }
+
+    /// Parses a date, see `chrono::format::strftime` for the format syntax (e.g. `%Y-%m-%d`).
+    pub fn to_date(&self, cell: Vec<u8>, format: &str) -> Result<NaiveDate, CsvError> {
+        let cell = self.to_string(cell)?;
+        NaiveDate::parse_from_str(&cell, format).map_err(|e| ParseError::new(e, Some(&cell)).into())
+    }
+
+    /// Parses a date and time, e.g. with the format `%Y-%m-%d %H:%M:%S`.
+    pub fn to_datetime(&self, cell: Vec<u8>, format: &str) -> Result<NaiveDateTime, CsvError> {
+        let cell = self.to_string(cell)?;
+        NaiveDateTime::parse_from_str(&cell, format)
+            .map_err(|e| ParseError::new(e, Some(&cell)).into())
+    }
+
+    /// Converts the cell with `convert` unless it's one of the null tokens.
+    ///
+    /// # Example
+    /// ```
+    /// # use process_csv::CellParser;
+    /// let parser = CellParser::default();
+    ///
+    /// assert_eq!(parser.to_option(b"NA".to_vec(), CellParser::to_float).unwrap(), None);
+    /// assert_eq!(parser.to_option(b"2.5".to_vec(), CellParser::to_float).unwrap(), Some(2.5));
+    /// ```
+    pub fn to_option<T, F>(&self, cell: Vec<u8>, convert: F) -> Result<Option<T>, CsvError>
+    where
+        F: FnOnce(&Self, Vec<u8>) -> Result<T, CsvError>,
+    {
+        if self.is_null(&cell)? {
+            return Ok(None);
+        }
+        convert(self, cell).map(Some)
+    }
+
+    pub fn is_null(&self, cell: &[u8]) -> Result<bool, CsvError> {
+        let cell = self.to_string(cell.to_vec())?;
+        Ok(self.nulls.contains(&cell))
+    }
+
+    /// Whether the quote opening `cell` is closed. A quote left open by the end of the file
+    /// is taken literally unless the reader is strict, see `Recovery`, and so is the cell.
+    fn is_closed(&self, cell: &[u8]) -> bool {
+        let Dialect { quote, escape, .. } = self.dialect;
+
+        let mut r = 1;
+        while r < cell.len() {
+            if Some(cell[r]) == escape && r + 1 < cell.len() {
+                r += 2;
+            } else if cell[r] == quote {
+                if cell.get(r + 1) != Some(&quote) {
+                    return true;
+                }
+                r += 2;
+            } else {
+                r += 1;
+            }
+        }
+        false
     }
 
     /// Normalizes a quoted CSV cell:
     /// - Removes enclosing quotes
-    /// - Replaces double quotes ("") with a single quote (")
+    /// - Replaces doubled quotes (`""`) with a single quote (`"`)
+    /// - Replaces escaped bytes (e.g., `\"`) with the byte itself, when the dialect has an escape
     ///
-    /// `n`: number of characters to trim from start/end (e.g., 1 or 2)
-    fn normalize(cell: &mut Vec<u8>, n: Trim) {
-        let n = n as usize;
-        if cell.len() < n + 1 {
-            return cell.clear();
-        }
+    /// Bytes found after the closing quote are kept as they are.
+    fn normalize(&self, cell: &mut Vec<u8>) {
+        let Dialect { quote, escape, .. } = self.dialect;
 
         let mut w = 0;
         let mut r = 1;
+        let mut between_quotes = true;
 
-        while r < cell.len() - n {
-            cell[w] = cell[r];
-
-            if cell[r] == QUOTES && cell[r + 1] == QUOTES {
-                r += 1;
+        while r < cell.len() {
+            if between_quotes {
+                if Some(cell[r]) == escape && r + 1 < cell.len() {
+                    r += 1;
+                } else if cell[r] == quote {
+                    if cell.get(r + 1) != Some(&quote) {
+                        between_quotes = false;
+                        r += 1;
+                        continue;
+                    }
+                    r += 1;
+                }
             }
 
+            cell[w] = cell[r];
             w += 1;
             r += 1;
         }
 
         cell.truncate(w);
     }
+
+    fn trim(cell: &mut Vec<u8>) {
+        let end = cell
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        cell.truncate(end);
+
+        let start = cell
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(cell.len());
+        cell.drain(..start);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unquote_cells() {
+        let parser = CellParser::default();
+
+        assert_eq!(parser.to_string(b"plain".to_vec()).unwrap(), "plain");
+        assert_eq!(parser.to_string(b"\"a,b\"".to_vec()).unwrap(), "a,b");
+        assert_eq!(
+            parser.to_string(b"\"say \"\"hi\"\"\"".to_vec()).unwrap(),
+            "say \"hi\""
+        );
+        assert_eq!(parser.to_string(b"\"\"".to_vec()).unwrap(), "");
+        assert_eq!(parser.to_string(b"\"open".to_vec()).unwrap(), "\"open");
+    }
+
+    #[test]
+    fn dialect_quote_and_escape() {
+        let parser = CellParser::from(Dialect {
+            quote: b'\'',
+            escape: Some(b'\\'),
+            ..Dialect::default()
+        });
+
+        assert_eq!(parser.to_string(b"'it\\'s'".to_vec()).unwrap(), "it's");
+        assert_eq!(parser.to_string(b"'a\"b'".to_vec()).unwrap(), "a\"b");
+    }
+
+    #[test]
+    fn trim_whitespace() {
+        let parser = CellParser::from(Dialect {
+            trim: Trim::Whitespace,
+            ..Dialect::default()
+        });
+
+        assert_eq!(parser.to_string(b"  a b \t".to_vec()).unwrap(), "a b");
+        assert_eq!(parser.to_string(b" \" a \" ".to_vec()).unwrap(), " a ");
+        assert_eq!(parser.parse::<u8>(b" 42 ".to_vec()).unwrap(), 42);
+        assert_eq!(parser.to_string(b"   ".to_vec()).unwrap(), "");
+    }
+
+    #[test]
+    fn conversion_errors() {
+        let parser = CellParser::default();
+
+        assert!(matches!(
+            parser.to_string(vec![b'a', 0xff]),
+            Err(CsvError::Utf8 { .. })
+        ));
+
+        let Err(CsvError::Parse(e)) = parser.parse::<u8>(b"3O".to_vec()) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(e.value(), Some("3O"));
+    }
+
+    #[test]
+    fn floats_and_decimal_separators() {
+        let parser = CellParser::default();
+        assert_eq!(parser.to_float(b"-1.5e3".to_vec()).unwrap(), -1500.0);
+        assert!(parser.to_float(b"1,5".to_vec()).is_err());
+
+        let parser = CellParser::default().with_decimal_separator(b',', Some(b'.'));
+        assert_eq!(parser.to_float(b"\"1.234,5\"".to_vec()).unwrap(), 1234.5);
+        assert_eq!(parser.to_float(b"0,25".to_vec()).unwrap(), 0.25);
+
+        let parser = CellParser::default().with_decimal_separator(b',', None);
+        let Err(CsvError::Parse(e)) = parser.to_float(b"1.5".to_vec()) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(e.value(), Some("1.5"));
+    }
+
+    #[test]
+    fn bool_tokens() {
+        let parser = CellParser::default();
+        assert!(parser.to_bool(b"Yes".to_vec()).unwrap());
+        assert!(!parser.to_bool(b"0".to_vec()).unwrap());
+        assert!(parser.to_bool(b"maybe".to_vec()).is_err());
+
+        let parser = CellParser::default().with_bool_tokens(&["sim"], &["nao"]);
+        assert!(parser.to_bool(b"SIM".to_vec()).unwrap());
+        assert!(parser.to_bool(b"yes".to_vec()).is_err());
+    }
+
+    #[test]
+    fn dates() {
+        let parser = CellParser::default();
+
+        assert_eq!(
+            parser.to_date(b"31/01/2024".to_vec(), "%d/%m/%Y").unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        );
+        assert!(parser.to_date(b"2024-02-30".to_vec(), "%Y-%m-%d").is_err());
+
+        let datetime = parser
+            .to_datetime(b"2024-01-31 08:30:00".to_vec(), "%Y-%m-%d %H:%M:%S")
+            .unwrap();
+        assert_eq!(datetime.to_string(), "2024-01-31 08:30:00");
+    }
+
+    #[test]
+    fn null_tokens() {
+        let parser = CellParser::default();
+        assert_eq!(
+            parser
+                .to_option(b"".to_vec(), CellParser::parse::<u8>)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            parser
+                .to_option(b"\"NULL\"".to_vec(), CellParser::to_bool)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            parser
+                .to_option(b"7".to_vec(), CellParser::parse::<u8>)
+                .unwrap(),
+            Some(7)
+        );
+        assert!(
+            parser
+                .to_option(b"x".to_vec(), CellParser::parse::<u8>)
+                .is_err()
+        );
+
+        let parser = CellParser::default().with_null_tokens(&["-"]);
+        assert_eq!(
+            parser
+                .to_option(b"-".to_vec(), CellParser::to_string)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            parser
+                .to_option(b"NA".to_vec(), CellParser::to_string)
+                .unwrap(),
+            Some("NA".to_string())
+        );
+    }
 }
@@ -0,0 +1,337 @@
+/// `Index` records where every Nth row of a file starts, so a reader can jump to a row
+/// without splitting the rows before it, see `CsvReader::seek_row`.
+///
+/// Positions come from the splitter, so they are quote-aware: a line break inside a
+/// quoted cell isn't mistaken for the start of a row. An index is saved next to its file,
+/// as `<file>.idx`, in a small binary format:
+///
+/// - the magic bytes `PCSVIDX3`,
+/// - the interval, the number of rows, the length of the file and the number of entries,
+/// - the delimiter, quote, escape and terminator of the dialect, 256 standing for no
+///   escape and for `Terminator::CRLF`, then 1 or 0 for the whitespace trim and for the
+///   header row,
+/// - the recovery policy, 0 for `Strict`, 1 for `SkipRow` and 2 for `BestEffort`,
+/// - the record, line and byte of each entry,
+///
+/// every number being a little-endian `u64`.
+///
+/// The positions only hold for the dialect, header setting and recovery policy the index
+/// was built with, a reader with others rejects it. A stale index is told by the length
+/// of its file alone: an edit that keeps the length goes unnoticed.
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{CsvError, CsvReader, Dialect, Position, Recovery, Terminator, Trim};
+
+const MAGIC: &[u8; 8] = b"PCSVIDX3";
+
+/// Stands for no escape byte and for `Terminator::CRLF` in an index file.
+const NO_BYTE: u64 = 256;
+
+/// Default number of rows between two entries of an index.
+pub const INDEX_EVERY: u64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    every: u64,
+    rows: u64,
+    len: u64,
+    dialect: Dialect,
+    has_headers: bool,
+    recovery: Recovery,
+    positions: Vec<Position>,
+}
+
+impl Index {
+    /// Builds the index of the rows of `reader`, which must not have read any row yet.
+    /// Rows are numbered from 0, the header row excluded, like in `slice`.
+    ///
+    /// The reader is read to the end. Its byte positions have to be offsets in the file
+    /// for the index to be used, which rules out transcoded inputs.
+    ///
+    /// # Parameters
+    /// - `every`: Number of rows between two entries. Seeking splits up to `every - 1`
+    ///   rows past the entry, the index holds `rows / every` entries.
+    ///
+    /// # Returns
+    /// - `Err(CsvError::InvalidArgument)` when `every` is 0.
+    ///
+    /// # Example
+    /// ```
+    /// use process_csv::{CsvReader, Dialect, Index};
+    ///
+    /// let content = b"Name,Age\nAlice,25\nBob,30\nCarol,40\n";
+    /// let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+    ///
+    /// let index = Index::build(&mut reader, 2).unwrap();
+    /// assert_eq!(index.rows(), 3);
+    /// assert_eq!(index.locate(1).unwrap().byte, 9);
+    /// assert_eq!(index.locate(2).unwrap().byte, 25);
+    /// ```
+    pub fn build<R: Read>(reader: &mut CsvReader<R>, every: u64) -> Result<Index, CsvError> {
+        if every == 0 {
+            let message = "An index needs at least one row between entries";
+            return Err(CsvError::InvalidArgument(message.to_string()));
+        }
+
+        let (dialect, has_headers) = (reader.dialect(), reader.has_headers());
+        let recovery = reader.recovery();
+        let mut positions = Vec::new();
+        let mut rows = 0;
+        while let Some(row) = reader.next_row() {
+            let row = row?;
+            if rows % every == 0 {
+                positions.push(row.position());
+            }
+            rows += 1;
+        }
+
+        Ok(Index {
+            every,
+            rows,
+            len: reader.end().byte,
+            dialect,
+            has_headers,
+            recovery,
+            positions,
+        })
+    }
+
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+
+    /// Number of rows of the file, the header row excluded.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Length of the file the index was built from, in bytes.
+    pub fn file_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Dialect of the reader the index was built with.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Whether the reader the index was built with skipped a header row.
+    pub fn has_headers(&self) -> bool {
+        self.has_headers
+    }
+
+    /// Recovery policy of the reader the index was built with, which decides what rows
+    /// are counted.
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+
+    /// Returns the closest entry at or before `row`, `None` past the last row.
+    pub fn locate(&self, row: u64) -> Option<Position> {
+        if row >= self.rows {
+            return None;
+        }
+        self.positions.get((row / self.every) as usize).copied()
+    }
+
+    /// Sidecar file the index of `path` is saved to.
+    pub fn sidecar(path: impl AsRef<Path>) -> PathBuf {
+        let mut sidecar = path.as_ref().as_os_str().to_owned();
+        sidecar.push(".idx");
+        PathBuf::from(sidecar)
+    }
+
+    /// Saves the index next to the file at `path`, see `sidecar`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CsvError> {
+        let mut out = BufWriter::new(File::create(Index::sidecar(path))?);
+        self.write_to(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Loads the index saved next to the file at `path`.
+    ///
+    /// # Returns
+    /// - `Ok(None)` when there is no index for the file.
+    /// - `Err(CsvError::Io)` when the sidecar isn't an index.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Index>, CsvError> {
+        let file = match File::open(Index::sidecar(path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(Index::read_from(BufReader::new(file))?))
+    }
+
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        let counts = [self.every, self.rows, self.len, self.positions.len() as u64];
+        let Dialect {
+            delimiter,
+            quote,
+            escape,
+            terminator,
+            trim,
+        } = self.dialect;
+        let terminator = match terminator {
+            Terminator::CRLF => NO_BYTE,
+            Terminator::Any(byte) => byte as u64,
+        };
+        let dialect = [
+            delimiter as u64,
+            quote as u64,
+            escape.map_or(NO_BYTE, u64::from),
+            terminator,
+            (trim == Trim::Whitespace) as u64,
+            self.has_headers as u64,
+            match self.recovery {
+                Recovery::Strict => 0,
+                Recovery::SkipRow => 1,
+                Recovery::BestEffort => 2,
+            },
+        ];
+        for n in counts.into_iter().chain(dialect) {
+            out.write_all(&n.to_le_bytes())?;
+        }
+        for position in &self.positions {
+            for n in [position.record, position.line, position.byte] {
+                out.write_all(&n.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<Index> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Not an index file");
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic).map_err(|_| invalid())?;
+        if &magic != MAGIC {
+            return Err(invalid());
+        }
+        let mut next = || {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes).map_err(|_| invalid())?;
+            Ok::<_, io::Error>(u64::from_le_bytes(bytes))
+        };
+
+        let (every, rows, len, entries) = (next()?, next()?, next()?, next()?);
+        if every == 0 || entries != rows.div_ceil(every) {
+            return Err(invalid());
+        }
+        let byte = |n: u64| u8::try_from(n).map_err(|_| invalid());
+        let flag = |n: u64| match n {
+            0 | 1 => Ok(n == 1),
+            _ => Err(invalid()),
+        };
+        let (delimiter, quote, escape, terminator) = (next()?, next()?, next()?, next()?);
+        let dialect = Dialect {
+            delimiter: byte(delimiter)?,
+            quote: byte(quote)?,
+            escape: match escape {
+                NO_BYTE => None,
+                escape => Some(byte(escape)?),
+            },
+            terminator: match terminator {
+                NO_BYTE => Terminator::CRLF,
+                terminator => Terminator::Any(byte(terminator)?),
+            },
+            trim: match flag(next()?)? {
+                true => Trim::Whitespace,
+                false => Trim::None,
+            },
+        };
+        let has_headers = flag(next()?)?;
+        let recovery = match next()? {
+            0 => Recovery::Strict,
+            1 => Recovery::SkipRow,
+            2 => Recovery::BestEffort,
+            _ => return Err(invalid()),
+        };
+        let positions = (0..entries)
+            .map(|_| {
+                Ok(Position {
+                    record: next()?,
+                    line: next()?,
+                    byte: next()?,
+                })
+            })
+            .collect::<io::Result<Vec<Position>>>()?;
+
+        Ok(Index {
+            every,
+            rows,
+            len,
+            dialect,
+            has_headers,
+            recovery,
+            positions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    const USERS: &[u8] = b"Name,Note\n\
+        Alice,\"two\nlines\"\n\
+        Bob,x\n\
+        Carol,y\n\
+        Dave,z\n";
+
+    #[test]
+    fn entries() {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let index = Index::build(&mut reader, 2).unwrap();
+
+        assert_eq!((index.rows(), index.file_len()), (4, USERS.len() as u64));
+        let carol = index.locate(2).unwrap();
+        assert_eq!((carol.record, carol.line, carol.byte), (3, 5, 34));
+        assert_eq!(index.locate(3), Some(carol));
+        assert_eq!(index.locate(4), None);
+
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let e = Index::build(&mut reader, 0).unwrap_err();
+        assert!(matches!(e, CsvError::InvalidArgument(_)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let index = Index::build(&mut reader, 3).unwrap();
+
+        let mut bytes = Vec::new();
+        index.write_to(&mut bytes).unwrap();
+        assert_eq!(Index::read_from(&bytes[..]).unwrap(), index);
+
+        let dialect = Dialect {
+            delimiter: b';',
+            escape: Some(b'\\'),
+            terminator: Terminator::Any(b'\n'),
+            trim: Trim::Whitespace,
+            ..Dialect::default()
+        };
+        let mut reader = CsvReader::from_reader(&b"a;b\nc;d\n"[..], dialect)
+            .without_headers()
+            .with_recovery(Recovery::SkipRow);
+        let other = Index::build(&mut reader, 3).unwrap();
+        let mut other_bytes = Vec::new();
+        other.write_to(&mut other_bytes).unwrap();
+        let other = Index::read_from(&other_bytes[..]).unwrap();
+        assert_eq!(
+            (other.dialect(), other.has_headers(), other.recovery()),
+            (dialect, false, Recovery::SkipRow)
+        );
+
+        assert!(Index::read_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Index::read_from(&b"Name,Note\n"[..]).is_err());
+        assert_eq!(
+            Index::sidecar("data/users.csv"),
+            PathBuf::from("data/users.csv.idx")
+        );
+    }
+}
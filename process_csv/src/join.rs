@@ -0,0 +1,533 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::iter;
+use std::str::FromStr;
+
+use crate::headers::column_of;
+use crate::sort::{Entry, Keys, Value};
+use crate::{CellParser, CsvError, CsvReader, CsvWriter, Headers, Position};
+
+/// Rows kept by `Joiner` besides the matching ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only the rows matching a row of the other side.
+    #[default]
+    Inner,
+    /// Every left row, with empty right cells when nothing matches.
+    Left,
+    /// Every row of both sides, with empty cells on the side that doesn't match.
+    Full,
+}
+
+impl FromStr for JoinKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "full" => Ok(JoinKind::Full),
+            _ => Err("Expected one of inner, left or full"),
+        }
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "left",
+            JoinKind::Full => "full",
+        };
+        f.pad(name)
+    }
+}
+
+/// Joins the rows of two files on one or more key columns.
+///
+/// Rows match when all their keys are equal, keys being compared like `Sorter` compares
+/// them, so `30` matches `30.0`. Each output row is the left row followed by the right
+/// row, and the header row is the left header followed by the right one.
+///
+/// Rows keep their cells in place, padded when the other side has no match: every row of
+/// an input has as many cells as its header, or as its first row when it has none.
+///
+/// By default the right rows are held in a hash table and the left rows are streamed,
+/// coming out in the order of the left file; hold the smaller side in memory. Inputs
+/// already sorted by their keys can be merged instead with `sorted`, in constant memory.
+///
+/// # Example
+/// ```
+/// use process_csv::{CsvReader, CsvWriter, Dialect, JoinKind, Joiner};
+///
+/// let users = b"Name,Country\nBob,UK\nAlice,FR\n";
+/// let countries = b"Code,Country\nUK,United Kingdom\n";
+/// let mut users = CsvReader::from_reader(&users[..], Dialect::default());
+/// let mut countries = CsvReader::from_reader(&countries[..], Dialect::default());
+/// let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+///
+/// Joiner::new(JoinKind::Inner, &["Country"], &["Code"])
+///     .join(&mut users, &mut countries, &mut out)
+///     .unwrap();
+/// assert_eq!(
+///     out.into_inner().unwrap(),
+///     b"Name,Country,Code,Country\r\nBob,UK,UK,United Kingdom\r\n"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Joiner {
+    kind: JoinKind,
+    left_on: Vec<String>,
+    right_on: Vec<String>,
+    hold_left: bool,
+    sorted: bool,
+}
+
+impl Joiner {
+    /// Joiner on the `left_on` columns of the left file and the `right_on` columns of the
+    /// right one, named after the header or given by 0-based index, in the same order.
+    pub fn new(kind: JoinKind, left_on: &[&str], right_on: &[&str]) -> Self {
+        Joiner {
+            kind,
+            left_on: left_on.iter().map(|k| k.to_string()).collect(),
+            right_on: right_on.iter().map(|k| k.to_string()).collect(),
+            hold_left: false,
+            sorted: false,
+        }
+    }
+
+    /// Holds the left rows in memory rather than the right ones, for a left file smaller
+    /// than the right one. Rows come out in the order of the right file then, followed by
+    /// the unmatched left rows.
+    pub fn holding_left(mut self) -> Self {
+        self.hold_left = true;
+        self
+    }
+
+    /// Merges inputs sorted in ascending order by their keys, as `Sorter` sorts them,
+    /// rather than holding one side in memory. Only the right rows sharing a key are held.
+    pub fn sorted(mut self) -> Self {
+        self.sorted = true;
+        self
+    }
+
+    /// Writes the joined rows of `left` and `right` to `out`, after the header row. Cells
+    /// are written as they are in the files, so both files and `out` should use the same
+    /// dialect.
+    ///
+    /// # Returns
+    /// - The number of rows written, the header row excluded.
+    /// - `Err(CsvError::UnknownColumn)` when a key isn't in the header.
+    /// - `Err(CsvError::InvalidArgument)` when there aren't as many right keys as left keys.
+    /// - `Err(CsvError::FieldCountMismatch)` when a row of an input without headers doesn't
+    ///   have as many cells as its first row.
+    /// - `Err(CsvError::Unsorted)` when merging and an input isn't sorted.
+    pub fn join<L: Read, R: Read, W: Write>(
+        &self,
+        left: &mut CsvReader<L>,
+        right: &mut CsvReader<R>,
+        out: &mut CsvWriter<W>,
+    ) -> Result<u64, CsvError> {
+        if self.left_on.len() != self.right_on.len() {
+            let message = "Expected as many right keys as left keys";
+            return Err(CsvError::InvalidArgument(message.to_string()));
+        }
+
+        let left = Side::new(left, &self.left_on)?;
+        let right = Side::new(right, &self.right_on)?;
+        if left.headers.is_some() || right.headers.is_some() {
+            let headers = left.headers.iter().flat_map(Headers::iter);
+            out.write_record(headers.chain(right.headers.iter().flat_map(Headers::iter)))?;
+        }
+
+        match (self.sorted, self.hold_left) {
+            (true, _) => merge(self.kind, left, right, out),
+            (false, false) => hash_join(self.kind, right, left, false, out),
+            (false, true) => hash_join(self.kind, left, right, true, out),
+        }
+    }
+}
+
+/// An input of the join, with its keys and the number of cells of its rows.
+struct Side<'r, R> {
+    reader: &'r mut CsvReader<R>,
+    headers: Option<Headers>,
+    keys: Keys,
+    width: usize,
+    /// First row, read ahead to know the width of an input without headers.
+    first: Option<(Entry, Position)>,
+    last: Option<Vec<Value>>,
+}
+
+impl<'r, R: Read> Side<'r, R> {
+    fn new(reader: &'r mut CsvReader<R>, on: &[String]) -> Result<Self, CsvError> {
+        let parser = CellParser::from(reader.dialect());
+        let headers = reader.headers()?.cloned();
+        let columns = on
+            .iter()
+            .map(|name| column_of(headers.as_ref(), name))
+            .collect::<Result<Vec<usize>, CsvError>>()?;
+
+        let mut side = Side {
+            reader,
+            width: headers.as_ref().map_or(0, Headers::len),
+            headers,
+            keys: Keys {
+                columns,
+                parser,
+                reverse: false,
+            },
+            first: None,
+            last: None,
+        };
+        if side.headers.is_none()
+            && let Some(row) = side.reader.next_row()
+        {
+            let row = row?;
+            side.width = row.len();
+            side.first = Some((side.keys.entry(&row)?, row.position()));
+        }
+        Ok(side)
+    }
+
+    fn next(&mut self) -> Result<Option<Entry>, CsvError> {
+        Ok(self.next_row()?.map(|(entry, _)| entry))
+    }
+
+    /// Next row of an input sorted by its keys.
+    fn next_sorted(&mut self) -> Result<Option<Entry>, CsvError> {
+        let Some((entry, position)) = self.next_row()? else {
+            return Ok(None);
+        };
+        if self.last.as_ref().is_some_and(|last| entry.key < *last) {
+            return Err(CsvError::Unsorted { position });
+        }
+        self.last = Some(entry.key.clone());
+        Ok(Some(entry))
+    }
+
+    fn next_row(&mut self) -> Result<Option<(Entry, Position)>, CsvError> {
+        if let Some(first) = self.first.take() {
+            return Ok(Some(first));
+        }
+        let Some(row) = self.reader.next_row() else {
+            return Ok(None);
+        };
+        let row = row?;
+        // The reader checks the rows against the header, if any.
+        if row.len() != self.width {
+            return Err(CsvError::FieldCountMismatch {
+                position: row.position(),
+                expected: self.width,
+                found: row.len(),
+            });
+        }
+        Ok(Some((self.keys.entry(&row)?, row.position())))
+    }
+}
+
+/// Joins by holding the rows of `held` in a hash table and looking up the rows of
+/// `streamed` in it. `held_left` tells which side is the left one.
+fn hash_join<H: Read, S: Read, W: Write>(
+    kind: JoinKind,
+    mut held: Side<H>,
+    mut streamed: Side<S>,
+    held_left: bool,
+    out: &mut CsvWriter<W>,
+) -> Result<u64, CsvError> {
+    let (keep_held, keep_streamed) = match (kind, held_left) {
+        (JoinKind::Inner, _) => (false, false),
+        (JoinKind::Left, true) => (true, false),
+        (JoinKind::Left, false) => (false, true),
+        (JoinKind::Full, _) => (true, true),
+    };
+
+    let mut table: HashMap<Vec<Value>, Vec<usize>> = HashMap::new();
+    let mut rows = Vec::new();
+    while let Some(Entry { key, cells }) = held.next()? {
+        table.entry(key).or_default().push(rows.len());
+        rows.push(cells);
+    }
+    let mut matched = vec![false; rows.len()];
+
+    let mut written = 0;
+    while let Some(entry) = streamed.next()? {
+        let matches = table.get(&entry.key).map_or(&[][..], Vec::as_slice);
+        for &i in matches {
+            matched[i] = true;
+            let (left, right) = match held_left {
+                true => ((&rows[i], held.width), (&entry.cells, streamed.width)),
+                false => ((&entry.cells, streamed.width), (&rows[i], held.width)),
+            };
+            write_joined(out, Some(left.0), left.1, Some(right.0), right.1)?;
+        }
+        if matches.is_empty() && keep_streamed {
+            match held_left {
+                true => write_joined(out, None, held.width, Some(&entry.cells), streamed.width)?,
+                false => write_joined(out, Some(&entry.cells), streamed.width, None, held.width)?,
+            }
+        }
+        written += matches.len().max(keep_streamed as usize) as u64;
+    }
+
+    if keep_held {
+        for (cells, _) in rows.iter().zip(&matched).filter(|(_, matched)| !**matched) {
+            match held_left {
+                true => write_joined(out, Some(cells), held.width, None, streamed.width)?,
+                false => write_joined(out, None, streamed.width, Some(cells), held.width)?,
+            }
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// Joins inputs sorted by their keys, holding the right rows of one key at a time.
+fn merge<L: Read, R: Read, W: Write>(
+    kind: JoinKind,
+    mut left: Side<L>,
+    mut right: Side<R>,
+    out: &mut CsvWriter<W>,
+) -> Result<u64, CsvError> {
+    let mut written = 0;
+    let mut l = left.next_sorted()?;
+    let mut r = right.next_sorted()?;
+    loop {
+        let ordering = match (&l, &r) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(a), Some(b)) => a.key.cmp(&b.key),
+        };
+
+        match ordering {
+            Ordering::Less => {
+                let entry = l.take().expect("compared to the right row");
+                if kind != JoinKind::Inner {
+                    write_joined(out, Some(&entry.cells), left.width, None, right.width)?;
+                    written += 1;
+                }
+                l = left.next_sorted()?;
+            }
+            Ordering::Greater => {
+                let entry = r.take().expect("compared to the left row");
+                if kind == JoinKind::Full {
+                    write_joined(out, None, left.width, Some(&entry.cells), right.width)?;
+                    written += 1;
+                }
+                r = right.next_sorted()?;
+            }
+            Ordering::Equal => {
+                let Entry { key, cells } = r.take().expect("compared to the left row");
+                let mut group = vec![cells];
+                r = right.next_sorted()?;
+                while let Some(entry) = r.take_if(|entry| entry.key == key) {
+                    group.push(entry.cells);
+                    r = right.next_sorted()?;
+                }
+
+                while let Some(entry) = l.take_if(|entry| entry.key == key) {
+                    for cells in &group {
+                        write_joined(
+                            out,
+                            Some(&entry.cells),
+                            left.width,
+                            Some(cells),
+                            right.width,
+                        )?;
+                    }
+                    written += group.len() as u64;
+                    l = left.next_sorted()?;
+                }
+            }
+        }
+    }
+    Ok(written)
+}
+
+/// Writes the left cells followed by the right ones, each side padded with empty cells
+/// to its width, so a missing or short row doesn't shift the cells of the other side.
+fn write_joined<W: Write>(
+    out: &mut CsvWriter<W>,
+    left: Option<&Vec<Vec<u8>>>,
+    left_width: usize,
+    right: Option<&Vec<Vec<u8>>>,
+    right_width: usize,
+) -> Result<(), CsvError> {
+    fn padded(cells: Option<&Vec<Vec<u8>>>, width: usize) -> impl Iterator<Item = &[u8]> {
+        let cells = cells.map_or(&[][..], Vec::as_slice);
+        let padding = width.saturating_sub(cells.len());
+        cells
+            .iter()
+            .map(Vec::as_slice)
+            .chain(iter::repeat_n(&[][..], padding))
+    }
+
+    out.write_raw_record(padded(left, left_width).chain(padded(right, right_width)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    const USERS: &[u8] = b"Name,Country\n\
+        Alice,US\n\
+        Bob,UK\n\
+        Carol,FR\n\
+        Dave,UK\n";
+
+    const COUNTRIES: &[u8] = b"Code,Country\n\
+        DE,Germany\n\
+        UK,United Kingdom\n\
+        US,\"United States\"\n";
+
+    fn joined(joiner: Joiner, left: &[u8], right: &[u8]) -> String {
+        let mut left = CsvReader::from_reader(left, Dialect::default());
+        let mut right = CsvReader::from_reader(right, Dialect::default());
+        let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+
+        joiner.join(&mut left, &mut right, &mut out).unwrap();
+        String::from_utf8(out.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hash_joins() {
+        let joiner = Joiner::new(JoinKind::Inner, &["Country"], &["Code"]);
+        assert_eq!(
+            joined(joiner.clone(), USERS, COUNTRIES),
+            "Name,Country,Code,Country\r\n\
+            Alice,US,US,\"United States\"\r\n\
+            Bob,UK,UK,United Kingdom\r\n\
+            Dave,UK,UK,United Kingdom\r\n"
+        );
+
+        let joiner = Joiner::new(JoinKind::Left, &["1"], &["0"]);
+        assert_eq!(
+            joined(joiner.clone(), USERS, COUNTRIES),
+            "Name,Country,Code,Country\r\n\
+            Alice,US,US,\"United States\"\r\n\
+            Bob,UK,UK,United Kingdom\r\n\
+            Carol,FR,,\r\n\
+            Dave,UK,UK,United Kingdom\r\n"
+        );
+
+        let joiner = Joiner::new(JoinKind::Full, &["Country"], &["Code"]);
+        assert_eq!(
+            joined(joiner.clone(), USERS, COUNTRIES),
+            "Name,Country,Code,Country\r\n\
+            Alice,US,US,\"United States\"\r\n\
+            Bob,UK,UK,United Kingdom\r\n\
+            Carol,FR,,\r\n\
+            Dave,UK,UK,United Kingdom\r\n\
+            ,,DE,Germany\r\n"
+        );
+    }
+
+    #[test]
+    fn holding_left() {
+        let joiner = Joiner::new(JoinKind::Left, &["Code"], &["Country"]).holding_left();
+        assert_eq!(
+            joined(joiner, COUNTRIES, USERS),
+            "Code,Country,Name,Country\r\n\
+            US,\"United States\",Alice,US\r\n\
+            UK,United Kingdom,Bob,UK\r\n\
+            UK,United Kingdom,Dave,UK\r\n\
+            DE,Germany,,\r\n"
+        );
+
+        let joiner = Joiner::new(JoinKind::Full, &["Code"], &["Country"]).holding_left();
+        assert_eq!(
+            joined(joiner, COUNTRIES, USERS),
+            "Code,Country,Name,Country\r\n\
+            US,\"United States\",Alice,US\r\n\
+            UK,United Kingdom,Bob,UK\r\n\
+            ,,Carol,FR\r\n\
+            UK,United Kingdom,Dave,UK\r\n\
+            DE,Germany,,\r\n"
+        );
+    }
+
+    #[test]
+    fn sorted_merges() {
+        let left = b"Id,Name\n1,Alice\n2,Bob\n2,Bea\n4,Dave\n";
+        let right = b"Id,Score\n2.0,10\n2,20\n3,30\n4,40\n";
+
+        for kind in [JoinKind::Inner, JoinKind::Left, JoinKind::Full] {
+            let merged = joined(Joiner::new(kind, &["Id"], &["Id"]).sorted(), left, right);
+            let hashed = joined(Joiner::new(kind, &["Id"], &["Id"]), left, right);
+            let mut merged: Vec<&str> = merged.lines().collect();
+            let mut hashed: Vec<&str> = hashed.lines().collect();
+            merged.sort();
+            hashed.sort();
+            assert_eq!(merged, hashed, "{kind} join");
+        }
+        assert_eq!(
+            joined(
+                Joiner::new(JoinKind::Full, &["Id"], &["Id"]).sorted(),
+                left,
+                right
+            ),
+            "Id,Name,Id,Score\r\n\
+            1,Alice,,\r\n\
+            2,Bob,2.0,10\r\n\
+            2,Bob,2,20\r\n\
+            2,Bea,2.0,10\r\n\
+            2,Bea,2,20\r\n\
+            ,,3,30\r\n\
+            4,Dave,4,40\r\n"
+        );
+    }
+
+    #[test]
+    fn join_errors() {
+        let mut left = CsvReader::from_reader(USERS, Dialect::default());
+        let mut right = CsvReader::from_reader(COUNTRIES, Dialect::default());
+        let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+        let e = Joiner::new(JoinKind::Inner, &["Country"], &["Code"])
+            .sorted()
+            .join(&mut left, &mut right, &mut out)
+            .unwrap_err();
+        assert_eq!(e.position().unwrap().record, 2);
+
+        let mut left = CsvReader::from_reader(USERS, Dialect::default());
+        let mut right = CsvReader::from_reader(COUNTRIES, Dialect::default());
+        let e = Joiner::new(JoinKind::Inner, &["Country"], &["Name"])
+            .join(&mut left, &mut right, &mut out)
+            .unwrap_err();
+        assert!(matches!(e, CsvError::UnknownColumn(_)));
+
+        let mut left = CsvReader::from_reader(USERS, Dialect::default());
+        let mut right = CsvReader::from_reader(COUNTRIES, Dialect::default());
+        let e = Joiner::new(JoinKind::Inner, &["Country", "Name"], &["Code"])
+            .join(&mut left, &mut right, &mut out)
+            .unwrap_err();
+        assert!(matches!(e, CsvError::InvalidArgument(_)));
+    }
+
+    #[test]
+    fn without_headers() {
+        let left = b"1,Alice\n2,Bob,x\n";
+        let right = b"1,10\n3,30\n";
+        let join = |left: &[u8], right: &[u8]| {
+            let mut left = CsvReader::from_reader(left, Dialect::default()).without_headers();
+            let mut right = CsvReader::from_reader(right, Dialect::default()).without_headers();
+            let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+            Joiner::new(JoinKind::Full, &["0"], &["0"])
+                .join(&mut left, &mut right, &mut out)
+                .map(|_| String::from_utf8(out.into_inner().unwrap()).unwrap())
+        };
+
+        assert_eq!(
+            join(&left[..8], right).unwrap(),
+            "1,Alice,1,10\r\n,,3,30\r\n"
+        );
+        let e = join(left, right).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Expected 2 cells, found 3 at record 1, line 2, byte 8"
+        );
+    }
+}
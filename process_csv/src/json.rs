@@ -0,0 +1,208 @@
+use std::io::{self, Read, Write};
+
+use crate::{CellParser, CsvError, CsvReader};
+
+/// Layout of the JSON written by `to_json`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// A single array holding a value per row, one row per line.
+    #[default]
+    Array,
+    /// Newline-delimited JSON: a value per line and nothing around them.
+    Lines,
+}
+
+/// Writes the remaining rows of `reader` to `out` as JSON, streaming them one at a time.
+///
+/// Each row is an object keyed by the header, in the order of the columns, or an array of
+/// its cells when the reader has no headers. Cells are unquoted and trimmed like with
+/// `CellParser`.
+///
+/// # Parameters
+/// - `types`: Writes numbers, `true`, `false` and the null tokens of `CellParser` as JSON
+///   numbers, booleans and nulls rather than strings. Numbers are written as they are in
+///   the file, so large integers don't lose precision, and only when they are valid JSON
+///   numbers: `007` or `1e` stay strings.
+///
+/// # Returns
+/// - The number of rows written.
+///
+/// # Example
+/// ```
+/// use process_csv::{CsvReader, Dialect, JsonFormat, to_json};
+///
+/// let content = b"Name,Age,Member\nBob,30,true\n\"Smith, Al\",NA,false\n";
+/// let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+/// let mut out = Vec::new();
+///
+/// to_json(&mut reader, &mut out, JsonFormat::Lines, true).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "{\"Name\":\"Bob\",\"Age\":30,\"Member\":true}\n\
+///     {\"Name\":\"Smith, Al\",\"Age\":null,\"Member\":false}\n"
+/// );
+/// ```
+pub fn to_json<R: Read, W: Write>(
+    reader: &mut CsvReader<R>,
+    mut out: W,
+    format: JsonFormat,
+    types: bool,
+) -> Result<u64, CsvError> {
+    let parser = CellParser::from(reader.dialect());
+    let headers = reader.headers()?.cloned();
+    // Keys are encoded once, with their colon.
+    let keys = match &headers {
+        Some(headers) => headers
+            .iter()
+            .map(|name| Ok(format!("{}:", serde_json::to_string(name)?)))
+            .collect::<Result<Vec<String>, serde_json::Error>>()
+            .map_err(io::Error::from)?,
+        None => Vec::new(),
+    };
+
+    let mut rows = 0;
+    while let Some(row) = reader.next_row() {
+        let row = row?;
+        match (format, rows) {
+            (JsonFormat::Array, 0) => out.write_all(b"[\n")?,
+            (JsonFormat::Array, _) => out.write_all(b",\n")?,
+            (JsonFormat::Lines, _) => {}
+        }
+
+        let (open, close) = if headers.is_some() {
+            (b'{', b'}')
+        } else {
+            (b'[', b']')
+        };
+        out.write_all(&[open])?;
+        for (i, raw) in row.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            if let Some(key) = keys.get(i) {
+                out.write_all(key.as_bytes())?;
+            }
+
+            let header = headers.as_ref().and_then(|h| h.get(i));
+            let value = parser
+                .to_string(raw.to_vec())
+                .map_err(|e| e.at(row.position()).in_cell(i, header, raw))?;
+            write_value(&mut out, &value, types.then_some(&parser))?;
+        }
+        out.write_all(&[close])?;
+
+        if format == JsonFormat::Lines {
+            out.write_all(b"\n")?;
+        }
+        rows += 1;
+    }
+
+    if format == JsonFormat::Array {
+        out.write_all(if rows == 0 { b"[]\n" } else { b"\n]\n" })?;
+    }
+    out.flush()?;
+    Ok(rows)
+}
+
+/// Writes a cell as a JSON string, or as the number, boolean or null it holds when
+/// `parser` is given to tell nulls apart.
+fn write_value<W: Write>(out: &mut W, value: &str, parser: Option<&CellParser>) -> io::Result<()> {
+    if let Some(parser) = parser {
+        if parser.is_null(value.as_bytes()).unwrap_or(false) {
+            return out.write_all(b"null");
+        }
+        if is_number(value) {
+            return out.write_all(value.as_bytes());
+        }
+        if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            return out.write_all(value.to_ascii_lowercase().as_bytes());
+        }
+    }
+    serde_json::to_writer(out, value).map_err(io::Error::from)
+}
+
+/// Whether `value` is a number in the JSON syntax: `-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?`.
+fn is_number(value: &str) -> bool {
+    let digits = |s: &str| s.bytes().take_while(u8::is_ascii_digit).count();
+
+    let mut rest = value.strip_prefix('-').unwrap_or(value);
+    let integer = digits(rest);
+    if integer == 0 || (integer > 1 && rest.starts_with('0')) {
+        return false;
+    }
+    rest = &rest[integer..];
+
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let n = digits(fraction);
+        if n == 0 {
+            return false;
+        }
+        rest = &fraction[n..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let n = digits(exponent);
+        if n == 0 {
+            return false;
+        }
+        rest = &exponent[n..];
+    }
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    const USERS: &[u8] = b"Name,Age,Zip,Note\n\
+        Alice,25,02139,\"says \"\"hi\"\"\"\n\
+        Bob,-1.5e3,,TRUE\n";
+
+    fn json(content: &[u8], has_headers: bool, format: JsonFormat, types: bool) -> String {
+        let mut reader = CsvReader::from_reader(content, Dialect::default());
+        if !has_headers {
+            reader = reader.without_headers();
+        }
+        let mut out = Vec::new();
+        to_json(&mut reader, &mut out, format, types).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn objects() {
+        assert_eq!(
+            json(USERS, true, JsonFormat::Array, false),
+            "[\n\
+            {\"Name\":\"Alice\",\"Age\":\"25\",\"Zip\":\"02139\",\"Note\":\"says \\\"hi\\\"\"},\n\
+            {\"Name\":\"Bob\",\"Age\":\"-1.5e3\",\"Zip\":\"\",\"Note\":\"TRUE\"}\n\
+            ]\n"
+        );
+        assert_eq!(
+            json(USERS, true, JsonFormat::Lines, true),
+            "{\"Name\":\"Alice\",\"Age\":25,\"Zip\":\"02139\",\"Note\":\"says \\\"hi\\\"\"}\n\
+            {\"Name\":\"Bob\",\"Age\":-1.5e3,\"Zip\":null,\"Note\":true}\n"
+        );
+        assert_eq!(json(b"Name\n", true, JsonFormat::Array, true), "[]\n");
+    }
+
+    #[test]
+    fn arrays_without_headers() {
+        assert_eq!(
+            json(b"a,1\nb,2\n", false, JsonFormat::Lines, true),
+            "[\"a\",1]\n[\"b\",2]\n"
+        );
+    }
+
+    #[test]
+    fn json_numbers() {
+        for number in ["0", "-0", "12", "1.5", "-0.25", "1e9", "2E-3", "1.5e+10"] {
+            assert!(is_number(number), "{number}");
+        }
+        for other in [
+            "", "-", "01", "1.", ".5", "1e", "+1", "1.5.2", "NaN", "inf", "0x10",
+        ] {
+            assert!(!is_number(other), "{other}");
+        }
+    }
+}
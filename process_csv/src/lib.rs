@@ -1,26 +1,345 @@
+pub mod aggregate;
+#[cfg(feature = "async")]
+pub mod async_reader;
+pub mod compression;
+pub mod de;
+pub mod dialect;
+pub mod encoding;
+pub mod error;
+pub mod expr;
+pub mod headers;
 pub mod helper;
+pub mod index;
+pub mod join;
+pub mod json;
+pub mod parallel;
+pub mod pipeline;
 pub mod reader;
+pub mod record;
+pub mod recovery;
+pub mod schema;
+pub mod ser;
+mod simd;
+pub mod sort;
+mod splitter;
+pub mod transform;
+pub mod validate;
+pub mod writer;
 
-use std::env;
+use std::{env, mem};
 
+use encoding_rs::UTF_8;
+
+pub use aggregate::{Accumulator, Agg, count, distinct, group_by, histogram};
+#[cfg(feature = "async")]
+pub use async_reader::AsyncCsvReader;
+pub use compression::Compression;
+pub use de::DeserializeRecords;
+pub use dialect::{Dialect, Terminator, Trim};
+pub use encoding::{Decoded, Encoding, Transcoder, decode};
+pub use error::{CsvError, ParseError, Position};
+pub use expr::{Expr, Filter};
+pub use headers::Headers;
 pub use helper::CellParser;
+pub use index::{INDEX_EVERY, Index};
+pub use join::{JoinKind, Joiner};
+pub use json::{JsonFormat, to_json};
+pub use parallel::ParRecords;
+pub use pipeline::{PIPELINE_BATCH, Pipeline};
 pub use reader::CsvReader;
+pub use reader::Records;
 pub use reader::YieldEvent;
+pub use record::{BorrowedRecord, Record};
+pub use recovery::{Anomaly, AnomalyKind, Recovery};
+pub use schema::{Column, ColumnType, Schema, infer_schema};
+pub use ser::SerializeError;
+pub use sort::Sorter;
+pub use transform::{filter, select, slice};
+pub use validate::{Report, Rule, Validator};
+pub use writer::CsvWriter;
 
 const LF: u8 = 10;
 const CR: u8 = 13;
 const COMMA: u8 = 44;
 const QUOTES: u8 = 34;
 
+const INFER_ROWS: usize = 1000;
+
+#[derive(Clone)]
 pub struct Config {
     file_path: String,
     watermark: Option<usize>,
+    dialect: Dialect,
+    has_headers: bool,
+    workers: Option<usize>,
+    mmap: bool,
+    infer: Option<usize>,
+    encoding: &'static Encoding,
+    lossy: bool,
+    recovery: Recovery,
+    rules: Option<String>,
+    rejects: Option<String>,
+    command: Option<Command>,
+}
+
+/// Subcommand of the binary, given before the file name, e.g.
+/// `process_csv group-by Country --agg avg --of Age users.csv`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `count`: number of rows.
+    Count,
+    /// `group-by <col> [--agg count|sum|avg|min|max] [--of <col>]`, see `group_by`.
+    GroupBy {
+        key: String,
+        agg: Agg,
+        of: Option<String>,
+    },
+    /// `distinct <col>`, see `distinct`.
+    Distinct { column: String },
+    /// `histogram <col> [--width <w>]`, see `histogram`.
+    Histogram { column: String, width: Option<f64> },
+    /// `select <col>,<col>...`, see `select`.
+    Select { columns: Vec<String> },
+    /// `filter <expr>`, see `Expr`.
+    Filter { expr: String },
+    /// `sort <col>,<col>... [--reverse]`, see `Sorter`. `SORT_MEMORY` sets its memory
+    /// budget in bytes.
+    Sort {
+        columns: Vec<String>,
+        reverse: bool,
+        memory: Option<usize>,
+    },
+    /// `head <n>`: the first rows.
+    Head { rows: u64 },
+    /// `slice <start>..<end>`, either bound being optional, see `slice`. Seeks to the
+    /// start through the index of the file when there is one.
+    Slice { start: u64, end: Option<u64> },
+    /// `to-json [--ndjson] [--types]`, see `to_json`.
+    ToJson { format: JsonFormat, types: bool },
+    /// `index [--every <n>]`: saves an index of the file next to it, see `Index`.
+    Index { every: u64 },
+    /// `join <col>[:<col>],... <right-file> [--kind inner|left|full] [--sorted]`, see
+    /// `Joiner`. The file is the left side, `<col>:<col>` names a key of the file and the
+    /// matching key of `right-file` when they differ.
+    Join {
+        left_on: Vec<String>,
+        right_on: Vec<String>,
+        right: String,
+        kind: JoinKind,
+        sorted: bool,
+    },
+}
+
+/// Configuration reading the standard input, as `build_from` gives it without flags nor
+/// variables.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            file_path: "-".to_string(),
+            watermark: None,
+            dialect: Dialect::default(),
+            has_headers: true,
+            workers: None,
+            mmap: false,
+            infer: None,
+            encoding: UTF_8,
+            lossy: false,
+            recovery: Recovery::default(),
+            rules: None,
+            rejects: None,
+            command: None,
+        }
+    }
 }
+
 impl Config {
     pub fn build_from(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
         args.next();
 
-        let file_path = args.next().ok_or("Didn't get a file name")?;
+        let mut positional = Vec::new();
+        let mut infer = None;
+        let mut rules = None;
+        let mut rejects = None;
+        let mut agg = None;
+        let mut of = None;
+        let mut width = None;
+        let mut reverse = false;
+        let mut kind = None;
+        let mut sorted = false;
+        let mut every = None;
+        let mut ndjson = false;
+        let mut types = false;
+        while let Some(arg) = args.next() {
+            // `--flag value` is the same as `--flag=value` for the flags taking a value.
+            let arg = match arg.as_str() {
+                "--rules" | "--rejects" | "--agg" | "--of" | "--width" | "--kind" | "--every" => {
+                    let value = args.next().ok_or("Missing value after a flag")?;
+                    format!("{arg}={value}")
+                }
+                _ => arg,
+            };
+
+            if arg == "--infer" {
+                infer = Some(INFER_ROWS);
+            } else if let Some(rows) = arg.strip_prefix("--infer=") {
+                infer = Some(rows.parse().map_err(|_| "Failed to parse '--infer'")?);
+            } else if let Some(path) = arg.strip_prefix("--rules=") {
+                rules = Some(path.to_string());
+            } else if let Some(path) = arg.strip_prefix("--rejects=") {
+                rejects = Some(path.to_string());
+            } else if let Some(name) = arg.strip_prefix("--agg=") {
+                agg = Some(name.parse::<Agg>()?);
+            } else if let Some(column) = arg.strip_prefix("--of=") {
+                of = Some(column.to_string());
+            } else if let Some(val) = arg.strip_prefix("--width=") {
+                let val = val.parse::<f64>().ok().filter(|&w| w > 0.0);
+                width = Some(val.ok_or("Failed to parse '--width'")?);
+            } else if arg == "--reverse" {
+                reverse = true;
+            } else if let Some(name) = arg.strip_prefix("--kind=") {
+                kind = Some(name.parse::<JoinKind>()?);
+            } else if arg == "--sorted" {
+                sorted = true;
+            } else if arg == "--ndjson" {
+                ndjson = true;
+            } else if arg == "--types" {
+                types = true;
+            } else if let Some(rows) = arg.strip_prefix("--every=") {
+                let rows = rows.parse::<u64>().ok().filter(|&n| n > 0);
+                every = Some(rows.ok_or("Failed to parse '--every'")?);
+            } else if arg.starts_with("--") {
+                return Err("Unknown flag");
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        let arg = || {
+            positional
+                .get(1)
+                .cloned()
+                .ok_or("Expected an argument after the command")
+        };
+        let columns = || Ok::<_, &'static str>(arg()?.split(',').map(String::from).collect());
+        let command = match positional.first().map(String::as_str) {
+            Some("count") => Some(Command::Count),
+            Some("group-by") => Some(Command::GroupBy {
+                key: arg()?,
+                agg: agg.take().unwrap_or(Agg::Count),
+                of: of.take(),
+            }),
+            Some("distinct") => Some(Command::Distinct { column: arg()? }),
+            Some("histogram") => Some(Command::Histogram {
+                column: arg()?,
+                width: width.take(),
+            }),
+            Some("select") => Some(Command::Select {
+                columns: columns()?,
+            }),
+            Some("filter") => Some(Command::Filter { expr: arg()? }),
+            Some("sort") => Some(Command::Sort {
+                columns: columns()?,
+                reverse: mem::take(&mut reverse),
+                memory: env::var("SORT_MEMORY")
+                    .ok()
+                    .map(|val| val.parse().map_err(|_| "Failed to parse 'SORT_MEMORY'"))
+                    .transpose()?,
+            }),
+            Some("head") => Some(Command::Head {
+                rows: arg()?
+                    .parse()
+                    .map_err(|_| "Failed to parse the rows of 'head'")?,
+            }),
+            Some("slice") => {
+                let range = arg()?;
+                let (start, end) = range
+                    .split_once("..")
+                    .ok_or("Expected a range like 10..20 after 'slice'")?;
+                let bound = |b: &str| {
+                    b.parse::<u64>()
+                        .map_err(|_| "Failed to parse the range of 'slice'")
+                };
+                Some(Command::Slice {
+                    start: if start.is_empty() { 0 } else { bound(start)? },
+                    end: if end.is_empty() {
+                        None
+                    } else {
+                        Some(bound(end)?)
+                    },
+                })
+            }
+            Some("to-json") => Some(Command::ToJson {
+                format: if mem::take(&mut ndjson) {
+                    JsonFormat::Lines
+                } else {
+                    JsonFormat::Array
+                },
+                types: mem::take(&mut types),
+            }),
+            Some("index") => Some(Command::Index {
+                every: every.take().unwrap_or(INDEX_EVERY),
+            }),
+            Some("join") => {
+                let (left_on, right_on) = arg()?
+                    .split(',')
+                    .map(|key| match key.split_once(':') {
+                        Some((left, right)) => (left.to_string(), right.to_string()),
+                        None => (key.to_string(), key.to_string()),
+                    })
+                    .unzip();
+                Some(Command::Join {
+                    left_on,
+                    right_on,
+                    right: positional
+                        .get(2)
+                        .cloned()
+                        .ok_or("Expected the file to join with after the columns")?,
+                    kind: kind.take().unwrap_or_default(),
+                    sorted: mem::take(&mut sorted),
+                })
+            }
+            _ => None,
+        };
+        if agg.is_some() || of.is_some() {
+            return Err("'--agg' and '--of' need 'group-by'");
+        }
+        if width.is_some() {
+            return Err("'--width' needs 'histogram'");
+        }
+        if reverse {
+            return Err("'--reverse' needs 'sort'");
+        }
+        if kind.is_some() || sorted {
+            return Err("'--kind' and '--sorted' need 'join'");
+        }
+        if every.is_some() {
+            return Err("'--every' needs 'index'");
+        }
+        if ndjson || types {
+            return Err("'--ndjson' and '--types' need 'to-json'");
+        }
+        if let Some(Command::GroupBy { agg, of: None, .. }) = &command
+            && *agg != Agg::Count
+        {
+            return Err("'--agg' needs '--of' with a column of values");
+        }
+
+        let command_args = match &command {
+            None => 0,
+            Some(Command::Count | Command::ToJson { .. } | Command::Index { .. }) => 1,
+            Some(Command::Join { .. }) => 3,
+            Some(_) => 2,
+        };
+        let mut positional = positional.into_iter().skip(command_args);
+        let file_path = positional
+            .next()
+            .ok_or("Didn't get a file name, use '-' for stdin")?;
+        if positional.next().is_some() {
+            return Err("Expected a single file name");
+        }
+        if rejects.is_some() && rules.is_none() {
+            return Err("'--rejects' needs '--rules'");
+        }
         let watermark = env::var("WATERMARK")
             .ok()
             .map(|val| {
@@ -28,10 +347,138 @@ impl Config {
                     .map_err(|_| "Failed to parse 'WATERMARK'")
             })
             .transpose()?;
+        let dialect = Dialect::from_env()?;
+        let has_headers = env::var("HAS_HEADERS")
+            .ok()
+            .map(|val| {
+                val.parse::<bool>()
+                    .map_err(|_| "Failed to parse 'HAS_HEADERS'")
+            })
+            .transpose()?
+            .unwrap_or(true);
+        let workers = env::var("WORKERS")
+            .ok()
+            .map(|val| {
+                val.parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or("Failed to parse 'WORKERS'")
+            })
+            .transpose()?;
+        let mmap = env::var("MMAP")
+            .ok()
+            .map(|val| val.parse::<bool>().map_err(|_| "Failed to parse 'MMAP'"))
+            .transpose()?
+            .unwrap_or(false);
+
+        let encoding = match env::var("ENCODING") {
+            Ok(val) => Encoding::for_label(val.as_bytes()).ok_or("Failed to parse 'ENCODING'")?,
+            Err(_) => UTF_8,
+        };
+        let lossy = env::var("LOSSY")
+            .ok()
+            .map(|val| val.parse::<bool>().map_err(|_| "Failed to parse 'LOSSY'"))
+            .transpose()?
+            .unwrap_or(false);
+        let recovery = env::var("RECOVERY")
+            .ok()
+            .map(|val| {
+                val.parse::<Recovery>()
+                    .map_err(|_| "Failed to parse 'RECOVERY'")
+            })
+            .transpose()?
+            .unwrap_or_default();
 
         Ok(Config {
             file_path,
             watermark,
+            dialect,
+            has_headers,
+            workers,
+            mmap,
+            infer,
+            encoding,
+            lossy,
+            recovery,
+            rules,
+            rejects,
+            command,
         })
     }
+
+    /// File to read, `-` for the standard input.
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Same configuration, reading another file, e.g. the right file of `join`.
+    pub fn with_file_path(mut self, file_path: impl Into<String>) -> Self {
+        self.file_path = file_path.into();
+        self
+    }
+
+    /// Number of threads set with `WORKERS`, for `par_records` and `Pipeline`.
+    pub fn workers(&self) -> Option<usize> {
+        self.workers
+    }
+
+    /// Number of rows to infer the schema from, when `--infer` or `--infer=N` was given.
+    pub fn infer(&self) -> Option<usize> {
+        self.infer
+    }
+
+    /// TOML file of validation rules given with `--rules FILE`, see `Validator::from_toml`.
+    pub fn rules(&self) -> Option<&str> {
+        self.rules.as_deref()
+    }
+
+    /// File the rows failing the rules are written to, given with `--rejects FILE`.
+    pub fn rejects(&self) -> Option<&str> {
+        self.rejects.as_deref()
+    }
+
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(args: &[&str]) -> Result<Config, &'static str> {
+        Config::build_from(
+            ["process_csv"]
+                .iter()
+                .chain(args)
+                .map(|arg| arg.to_string()),
+        )
+    }
+
+    #[test]
+    fn valued_flags() {
+        let spaced = [
+            "--rules",
+            "rules.toml",
+            "--rejects",
+            "rejects.csv",
+            "data.csv",
+        ];
+        let joined = ["--rules=rules.toml", "--rejects=rejects.csv", "data.csv"];
+        for args in [&spaced[..], &joined[..]] {
+            let config = config(args).unwrap();
+            assert_eq!(config.rules(), Some("rules.toml"));
+            assert_eq!(config.rejects(), Some("rejects.csv"));
+            assert_eq!(config.file_path(), "data.csv");
+        }
+
+        assert_eq!(
+            config(&["data.csv", "--rules"]).err(),
+            Some("Missing value after a flag")
+        );
+        assert_eq!(
+            config(&["--rejects", "rejects.csv", "data.csv"]).err(),
+            Some("'--rejects' needs '--rules'")
+        );
+    }
 }
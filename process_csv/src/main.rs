@@ -1,8 +1,16 @@
-use std::sync::mpsc;
+use std::fs::{self, File};
 use std::time::Instant;
-use std::{env, mem, process, thread};
+use std::{env, io, process};
 
-use process_csv::{CellParser, Config, CsvReader, YieldEvent};
+use process_csv::{
+    CellParser, Command, Config, CsvError, CsvReader, CsvWriter, Expr, Index, Joiner, Pipeline,
+    Sorter, Validator, count, distinct, filter, group_by, histogram, infer_schema, select, slice,
+    to_json,
+};
+use serde::Deserialize;
+
+/// Length of the bar of the most frequent value printed by `histogram`.
+const HISTOGRAM_BAR: u64 = 40;
 
 fn main() {
     let config = Config::build_from(env::args()).unwrap_or_else(|err| {
@@ -10,61 +18,238 @@ fn main() {
         process::exit(1);
     });
 
-    let process_csv = CsvReader::build_from(config).unwrap_or_else(|err| {
+    let infer = config.infer();
+    let command = config.command().cloned();
+    let validator = config.rules().map(|path| {
+        let rules = fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("Problem to open rules: {err}");
+            process::exit(1);
+        });
+        Validator::from_toml(&rules).unwrap_or_else(|err| {
+            eprintln!("Problem parsing rules: {err}");
+            process::exit(1);
+        })
+    });
+    let rejects = config.rejects().map(|path| {
+        File::create(path).unwrap_or_else(|err| {
+            eprintln!("Problem to create rejects file: {err}");
+            process::exit(1);
+        })
+    });
+    // `join` opens its right file with the same settings.
+    let settings = config.clone();
+    let mut process_csv = CsvReader::build_from(config).unwrap_or_else(|err| {
         eprintln!("Problem to open file: {err}");
         process::exit(1);
     });
 
-    let start = Instant::now();
-    let (tx, rx) = mpsc::channel();
+    if let Some(rows) = infer {
+        let schema = infer_schema(&mut process_csv, rows).unwrap_or_else(|e| {
+            eprintln!("Application error: {e}");
+            process::exit(1);
+        });
+        println!("{schema}");
+        report_anomalies(&process_csv);
+        return;
+    }
 
-    thread::spawn(move || {
-        let mut arr = Vec::new();
+    if let Some(validator) = validator {
+        let report = match rejects {
+            Some(rejects) => validator.validate_with_rejects(&mut process_csv, rejects),
+            None => validator.validate(&mut process_csv),
+        };
+        let report = report.unwrap_or_else(|e| {
+            eprintln!("Application error: {e}");
+            process::exit(1);
+        });
+        println!("{report}");
+        report_anomalies(&process_csv);
+        if !report.is_valid() {
+            process::exit(2);
+        }
+        return;
+    }
 
-        if let Err(e) = process_csv.process_file(|event| match event {
-            YieldEvent::NewCell(cell) => {
-                arr.push(cell);
-            }
-            YieldEvent::NewLine => {
-                let cap = arr.len();
-                tx.send(mem::replace(&mut arr, Vec::with_capacity(cap)))
-                    .unwrap();
-            }
-        }) {
+    if let Some(command) = command {
+        run(command, &settings, &mut process_csv).unwrap_or_else(|e| {
             eprintln!("Application error: {e}");
             process::exit(1);
-        } else {
-            tx.send(arr).unwrap();
-        };
-    });
+        });
+        report_anomalies(&process_csv);
+        return;
+    }
+
+    let start = Instant::now();
+    let mut pipeline = Pipeline::default();
+    if let Some(workers) = settings.workers() {
+        pipeline = pipeline.with_workers(workers);
+    }
+    let parser = CellParser::from(process_csv.dialect());
 
-    let _ = rx.recv();
+    pipeline
+        .run(
+            &mut process_csv,
+            |record| record.deserialize::<User>(&parser),
+            |user| {
+                println!(
+                    "user: {}\nage: {}\nmail: {}\ncountry: {}\n",
+                    user.name, user.age, user.mail, user.country
+                );
+            },
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Application error: {e}");
+            process::exit(1);
+        });
+    report_anomalies(&process_csv);
 
-    for received in rx {
-        let mut cells = received
-            .into_iter()
-            .map(|c| CellParser::to_string(c).unwrap());
+    let duration = start.elapsed();
+    println!("Time elapsed: {:?}", duration);
+}
 
-        let user = User {
-            name: cells.next().expect("Missing name"),
-            age: cells.next().expect("Missing age").parse().unwrap(),
-            mail: cells.next().expect("Missing mail"),
-            country: cells.next().expect("Missing country"),
-        };
+/// Prints the malformed quotes let through by the `RECOVERY` policy.
+fn report_anomalies(reader: &CsvReader) {
+    for anomaly in reader.anomalies() {
+        eprintln!("Recovered: {anomaly}");
+    }
+}
+
+/// Runs a subcommand, writing its result to the standard output.
+fn run(command: Command, config: &Config, reader: &mut CsvReader) -> Result<(), CsvError> {
+    let mut out = CsvWriter::from_writer(io::stdout().lock(), reader.dialect());
+
+    match command {
+        Command::Count => println!("{}", count(reader)?),
+        Command::GroupBy { key, agg, of } => {
+            let groups = group_by(reader, &key, agg, of.as_deref())?;
+            let value = match &of {
+                Some(of) => format!("{agg}({of})"),
+                None => agg.to_string(),
+            };
+            out.write_record([key, value])?;
+            for (group, value) in groups {
+                let value = value.map(|v| v.to_string()).unwrap_or_default();
+                out.write_record([group, value])?;
+            }
+        }
+        Command::Distinct { column } => {
+            out.write_record([&column])?;
+            for value in distinct(reader, &column)? {
+                out.write_record([value])?;
+            }
+        }
+        Command::Select { columns } => {
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            select(reader, &columns, &mut out)?;
+        }
+        Command::Filter { expr } => {
+            filter(reader, &Expr::parse(&expr)?, &mut out)?;
+        }
+        Command::Sort {
+            columns,
+            reverse,
+            memory,
+        } => {
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            let mut sorter = Sorter::new(&columns);
+            if reverse {
+                sorter = sorter.reversed();
+            }
+            if let Some(memory) = memory {
+                sorter = sorter.with_memory(memory);
+            }
+            sorter.sort(reader, &mut out)?;
+        }
+        Command::Head { rows } => {
+            slice(reader, 0..rows, &mut out)?;
+        }
+        Command::Slice { start, end } => {
+            let end = end.unwrap_or(u64::MAX);
+            let index = match config.file_path() {
+                "-" => None,
+                path => Index::load(path)?,
+            };
+            // Compressed or transcoded inputs can't seek, they are read from the start.
+            match index.filter(|_| reader.is_seekable()) {
+                Some(index) => {
+                    reader.seek_row(&index, start)?;
+                    slice(reader, 0..end.saturating_sub(start), &mut out)?;
+                }
+                None => {
+                    slice(reader, start..end, &mut out)?;
+                }
+            }
+        }
+        Command::ToJson { format, types } => {
+            to_json(
+                reader,
+                io::BufWriter::new(io::stdout().lock()),
+                format,
+                types,
+            )?;
+        }
+        Command::Index { every } => {
+            if config.file_path() == "-" {
+                let message = "The standard input can't be indexed";
+                return Err(CsvError::InvalidArgument(message.to_string()));
+            }
+            let index = Index::build(reader, every)?;
+            index.save(config.file_path())?;
+            println!(
+                "Indexed {} rows in {}",
+                index.rows(),
+                Index::sidecar(config.file_path()).display()
+            );
+        }
+        Command::Join {
+            left_on,
+            right_on,
+            right,
+            kind,
+            sorted,
+        } => {
+            let left_on: Vec<&str> = left_on.iter().map(String::as_str).collect();
+            let right_on: Vec<&str> = right_on.iter().map(String::as_str).collect();
+            let mut joiner = Joiner::new(kind, &left_on, &right_on);
+            if sorted {
+                joiner = joiner.sorted();
+            }
+            // Hold the smaller file in memory, the right one when the sizes aren't known.
+            let size = |path: &str| fs::metadata(path).map(|m| m.len()).ok();
+            if let (Some(left), Some(right)) = (size(config.file_path()), size(&right))
+                && left < right
+            {
+                joiner = joiner.holding_left();
+            }
+
+            let mut right = CsvReader::build_from(config.clone().with_file_path(right))?;
+            joiner.join(reader, &mut right, &mut out)?;
+        }
+        Command::Histogram { column, width } => {
+            let counts = histogram(reader, &column, width)?;
+            let label_width = counts
+                .iter()
+                .map(|(v, _)| v.chars().count())
+                .max()
+                .unwrap_or(0);
+            let most = counts.iter().map(|&(_, n)| n).max().unwrap_or(1);
 
-        println!(
-            "user: {}\nage: {}\nmail: {}\ncountry: {}\n",
-            user.name, user.age, user.mail, user.country
-        );
+            for (value, n) in &counts {
+                let bar = "#".repeat((n * HISTOGRAM_BAR).div_ceil(most) as usize);
+                println!("{value:<label_width$} | {bar} {n}");
+            }
+        }
     }
 
-    let duration = start.elapsed();
-    println!("Time elapsed: {:?}", duration);
+    out.flush()
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
 struct User {
     name: String,
     age: u8,
+    #[serde(rename = "Email")]
     mail: String,
     country: String,
 }
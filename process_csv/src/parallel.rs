@@ -0,0 +1,678 @@
+/// `ParRecords` parses a file on a pool of threads, see `CsvReader::par_records`.
+///
+/// The file is cut into byte ranges of the same size, which don't line up with rows.
+/// Knowing where the rows of a range start requires knowing whether its first byte is
+/// inside quotes, which depends on every byte before it. So the work is done in two jobs:
+///
+/// - `Scan` reads a range and finds out in which quote state it ends for each state it
+///   could start in. Chaining these in order gives the real state at the start of every
+///   range without parsing the file sequentially.
+/// - `Parse` skips to the first row starting inside the range and parses the rows that
+///   start inside it, reading past its end to finish the last one.
+///
+/// Positions in a range are counted from its first row, they are moved to their place in
+/// the file when the rows are handed out, in order. So are the anomalies found in the range,
+/// added to those of the reader.
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::vec;
+
+use crate::{
+    Anomaly, AnomalyKind, CR, CsvError, CsvReader, Dialect, Headers, LF, Position, Record,
+    Recovery, Terminator, Trim,
+};
+
+pub(crate) const RANGE_SIZE: u64 = 1024 * 1024; // 1MB
+
+/// What a worker needs to read a range of the file on its own.
+#[derive(Clone)]
+pub(crate) struct Source {
+    pub(crate) path: PathBuf,
+    pub(crate) dialect: Dialect,
+    pub(crate) recovery: Recovery,
+    pub(crate) watermark: usize,
+    pub(crate) headers: Option<Arc<Headers>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuoteState {
+    /// At the start of an unquoted cell, where a quote opens a quoted cell.
+    Start,
+    /// Inside an unquoted cell, where quotes are taken literally.
+    Unquoted,
+    Quoted,
+    /// Right after the escape byte inside quotes.
+    Escaped,
+    /// Right after a closing quote, where a quote opens the cell again.
+    Closed,
+}
+
+/// Quote state at the end of a range for each state it can start in, indexed by `QuoteState as usize`.
+type Transitions = [QuoteState; 5];
+
+impl QuoteState {
+    /// Same quote handling as the splitter.
+    fn step(self, byte: u8, dialect: &Dialect) -> QuoteState {
+        let blank = byte == b' ' || byte == b'\t';
+        match self {
+            QuoteState::Quoted if Some(byte) == dialect.escape => QuoteState::Escaped,
+            QuoteState::Quoted if byte == dialect.quote => QuoteState::Closed,
+            QuoteState::Quoted => QuoteState::Quoted,
+            QuoteState::Escaped => QuoteState::Quoted,
+            QuoteState::Start | QuoteState::Closed if byte == dialect.quote => QuoteState::Quoted,
+            _ if byte == dialect.delimiter || dialect.is_terminator(byte) => QuoteState::Start,
+            QuoteState::Start if blank && dialect.trim == Trim::Whitespace => QuoteState::Start,
+            _ => QuoteState::Unquoted,
+        }
+    }
+
+    fn is_quoted(self) -> bool {
+        matches!(self, QuoteState::Quoted | QuoteState::Escaped)
+    }
+}
+
+enum Job {
+    Scan(usize, Range<u64>),
+    Parse(usize, Range<u64>, QuoteState),
+}
+
+enum Done {
+    Scanned(usize, Result<Transitions, CsvError>),
+    Parsed(usize, Batch),
+    /// A job panicked, the panic is raised again by `ParRecords::next`.
+    Panicked(Box<dyn Any + Send>),
+}
+
+/// Rows that start inside a range.
+struct Batch {
+    /// Rows, and errors of the rows that couldn't be read such as a wrong number of cells.
+    records: Vec<Result<Record, CsvError>>,
+    /// Error that stopped the parsing, after the rows read before it.
+    error: Option<CsvError>,
+    /// Malformed quotes of the rows, see `CsvReader::anomalies`.
+    anomalies: Vec<Anomaly>,
+    /// Position of the first row after the range.
+    next: Position,
+    /// Whether the rows run to the end of the file, the ranges after this one holding none.
+    to_end: bool,
+}
+
+/// Iterator over the rows of a file parsed on a pool of threads, see `CsvReader::par_records`.
+pub struct ParRecords<'r> {
+    jobs: Option<Sender<Job>>,
+    done: Receiver<Done>,
+    workers: Vec<JoinHandle<()>>,
+    start: u64,
+    len: u64,
+    range_size: u64,
+    ranges: usize,
+    limit: usize,   // jobs sent and batches waiting to be handed out, at most
+    pending: usize, // jobs sent and batches waiting to be handed out
+    scanned: usize, // next range to scan
+    parsing: usize, // next range to parse
+    transitions: Vec<Option<Transitions>>,
+    states: Vec<QuoteState>, // quote state at the start of the ranges, as far as known
+    parsed: HashMap<usize, Batch>,
+    emitted: usize, // next range to hand out
+    base: Position, // position of the first row of the next range
+    batch: vec::IntoIter<Result<Record, CsvError>>,
+    error: Option<CsvError>,
+    anomalies: &'r mut Vec<Anomaly>, // of the reader
+}
+
+impl<'r> ParRecords<'r> {
+    pub(crate) fn new(
+        source: Source,
+        anomalies: &'r mut Vec<Anomaly>,
+        start: Position,
+        len: u64,
+        workers: usize,
+        range_size: u64,
+    ) -> Self {
+        Self::spawn(source, anomalies, start, len, workers, range_size, run)
+    }
+
+    /// Starts the workers, doing the jobs with `run`.
+    fn spawn(
+        source: Source,
+        anomalies: &'r mut Vec<Anomaly>,
+        start: Position,
+        len: u64,
+        workers: usize,
+        range_size: u64,
+        run: fn(&Source, Job) -> Done,
+    ) -> Self {
+        let ranges = len.saturating_sub(start.byte).div_ceil(range_size) as usize;
+        let workers = workers.clamp(1, ranges.max(1));
+
+        let (jobs, receiver) = mpsc::channel();
+        let (sender, done) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let limit = workers * 2;
+        let workers = (0..workers)
+            .map(|_| {
+                let (source, receiver, sender) =
+                    (source.clone(), Arc::clone(&receiver), sender.clone());
+                thread::spawn(move || work(source, receiver, sender, run))
+            })
+            .collect();
+
+        ParRecords {
+            jobs: Some(jobs),
+            done,
+            workers,
+            start: start.byte,
+            len,
+            range_size,
+            ranges,
+            limit,
+            pending: 0,
+            scanned: 0,
+            parsing: 0,
+            transitions: vec![None; ranges],
+            states: vec![QuoteState::Start],
+            parsed: HashMap::new(),
+            emitted: 0,
+            base: start,
+            batch: Vec::new().into_iter(),
+            error: None,
+            anomalies,
+        }
+    }
+
+    fn range(&self, i: usize) -> Range<u64> {
+        let start = self.start + i as u64 * self.range_size;
+        start..(start + self.range_size).min(self.len)
+    }
+
+    /// Sends jobs until `limit` is reached. Ranges whose start state is known are parsed
+    /// first, the others are scanned to find it out.
+    fn dispatch(&mut self) {
+        let Some(jobs) = &self.jobs else {
+            return;
+        };
+
+        while self.pending < self.limit {
+            let job = if self.parsing < self.states.len().min(self.ranges) {
+                self.parsing += 1;
+                let i = self.parsing - 1;
+                Job::Parse(i, self.range(i), self.states[i])
+            } else if self.scanned + 1 < self.ranges {
+                self.scanned += 1;
+                let i = self.scanned - 1;
+                Job::Scan(i, self.range(i))
+            } else {
+                break;
+            };
+
+            self.pending += 1;
+            let _ = jobs.send(job);
+        }
+    }
+
+    /// Stops handing out rows, after `error`.
+    fn fail(&mut self, error: CsvError) -> Option<Result<Record, CsvError>> {
+        self.emitted = self.ranges;
+        self.batch = Vec::new().into_iter();
+        Some(Err(error))
+    }
+}
+
+impl Iterator for ParRecords<'_> {
+    type Item = Result<Record, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.batch.next() {
+                return Some(row);
+            }
+            if let Some(e) = self.error.take() {
+                return self.fail(e);
+            }
+            if self.emitted == self.ranges {
+                return None;
+            }
+
+            if let Some(batch) = self.parsed.remove(&self.emitted) {
+                let base = self.base;
+                let records = batch.records.into_iter().map(|row| match row {
+                    Ok(mut record) => {
+                        record.rebase(base);
+                        Ok(record)
+                    }
+                    Err(e) => Err(e.rebase(base)),
+                });
+                let anomalies = batch.anomalies.into_iter();
+                self.anomalies
+                    .extend(anomalies.map(|anomaly| anomaly.rebase(base)));
+
+                self.batch = records.collect::<Vec<_>>().into_iter();
+                self.error = batch.error.map(|e| e.rebase(base));
+                self.base = batch.next.rebase(base);
+                self.emitted = if batch.to_end {
+                    self.ranges
+                } else {
+                    self.emitted + 1
+                };
+                self.pending -= 1;
+                continue;
+            }
+
+            self.dispatch();
+
+            match self.done.recv() {
+                Ok(Done::Scanned(i, Ok(transitions))) => {
+                    self.pending -= 1;
+                    self.transitions[i] = Some(transitions);
+
+                    while let Some(state) = self.states.last()
+                        && let Some(Some(transitions)) = self.transitions.get(self.states.len() - 1)
+                    {
+                        self.states.push(transitions[*state as usize]);
+                    }
+                }
+                Ok(Done::Scanned(_, Err(e))) => return self.fail(e),
+                Ok(Done::Parsed(i, batch)) => {
+                    self.parsed.insert(i, batch);
+                }
+                Ok(Done::Panicked(panic)) => panic::resume_unwind(panic),
+                Err(_) => return self.fail(io::Error::other("CSV worker thread panicked").into()),
+            }
+        }
+    }
+}
+
+impl Drop for ParRecords<'_> {
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(
+    source: Source,
+    jobs: Arc<Mutex<Receiver<Job>>>,
+    done: Sender<Done>,
+    run: fn(&Source, Job) -> Done,
+) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        // The other workers keep the channel open, a job lost to a panic would leave
+        // `next` waiting for it forever.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run(&source, job)));
+        let panicked = result.is_err();
+        let result = result.unwrap_or_else(Done::Panicked);
+
+        if done.send(result).is_err() || panicked {
+            return;
+        }
+    }
+}
+
+fn run(source: &Source, job: Job) -> Done {
+    match job {
+        Job::Scan(i, range) => Done::Scanned(i, scan(source, range)),
+        Job::Parse(i, range, state) => Done::Parsed(i, parse(source, range, state, i == 0)),
+    }
+}
+
+fn scan(source: &Source, range: Range<u64>) -> Result<Transitions, CsvError> {
+    let mut file = File::open(&source.path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+
+    let mut file = file.take(range.end - range.start);
+    let mut buf = vec![0; source.watermark];
+    let mut states = [
+        QuoteState::Start,
+        QuoteState::Unquoted,
+        QuoteState::Quoted,
+        QuoteState::Escaped,
+        QuoteState::Closed,
+    ];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(states);
+        }
+
+        for &byte in &buf[..n] {
+            for state in &mut states {
+                *state = state.step(byte, &source.dialect);
+            }
+        }
+    }
+}
+
+/// Parses the rows that start inside `range`, the first byte of the range being in `state`.
+/// The first range starts with a row.
+fn parse(source: &Source, range: Range<u64>, state: QuoteState, first: bool) -> Batch {
+    let mut batch = Batch {
+        records: Vec::new(),
+        error: None,
+        anomalies: Vec::new(),
+        next: Position {
+            record: 0,
+            line: 1,
+            byte: range.start,
+        },
+        to_end: false,
+    };
+
+    let start = match first {
+        true => Ok(range.start),
+        false => first_row_start(source, range.start, state),
+    };
+    let mut reader = match start {
+        Ok(start) if start >= range.end => return batch,
+        Ok(start) => CsvReader::resume_at(source, start),
+        Err(e) => Err(e.into()),
+    };
+    let reader = match &mut reader {
+        Ok(reader) => reader,
+        Err(_) => {
+            batch.error = reader.err();
+            return batch;
+        }
+    };
+
+    // A quote opened in the range and left open is taken literally once the end of the
+    // file is reached, which the ranges after it, scanned as quoted, can't know: their rows
+    // are read here.
+    let unterminated = |anomalies: &[Anomaly]| {
+        anomalies.iter().any(|anomaly| {
+            anomaly.kind == AnomalyKind::UnterminatedQuote && anomaly.position.byte < range.end
+        })
+    };
+
+    let mut next = None;
+    while let Some(row) = reader.next_row() {
+        let row = row.map(|row| row.to_record());
+        let position = match &row {
+            Ok(record) => record.position(),
+            Err(e) => e.position(),
+        };
+
+        if let Some(position) = position
+            && position.byte >= range.end
+            && !batch.to_end
+        {
+            batch.to_end = unterminated(reader.anomalies());
+            if !batch.to_end {
+                next = Some(position);
+                break;
+            }
+        }
+
+        // Like `records`, only an I/O error stops the reading.
+        if let Err(e @ CsvError::Io(_)) = row {
+            batch.error = Some(e);
+            break;
+        }
+        batch.records.push(row);
+    }
+
+    // Rows skipped with `Recovery::SkipRow` aren't handed out, their anomalies tell where
+    // they start: the first row after the range can be one of them.
+    let anomalies = reader.anomalies();
+    batch.to_end |= unterminated(anomalies);
+    if !batch.to_end {
+        let skipped = anomalies.iter().map(|anomaly| anomaly.position);
+        let after = skipped.chain(next).filter(|p| p.byte >= range.end);
+        batch.next = after.min_by_key(|p| p.byte).unwrap_or(batch.next);
+    }
+
+    // The reader splits ahead of the rows handed out, the anomalies of the rows after the
+    // range belong to the next one.
+    let end = if batch.to_end { u64::MAX } else { range.end };
+    batch.anomalies = anomalies
+        .iter()
+        .filter(|anomaly| anomaly.position.byte < end)
+        .copied()
+        .collect();
+
+    batch
+}
+
+/// Finds the start of the first row at or after `at`, the byte at `at` being in `state`.
+///
+/// # Returns
+/// - The end of the file when no row starts after `at`.
+fn first_row_start(source: &Source, mut at: u64, mut state: QuoteState) -> io::Result<u64> {
+    let dialect = source.dialect;
+
+    let mut file = File::open(&source.path)?;
+    file.seek(SeekFrom::Start(at - 1))?;
+    let mut bytes = BufReader::with_capacity(source.watermark, file).bytes();
+
+    // The byte before `at` is in the same state as `at` when it's a line terminator,
+    // so it tells whether a row starts right at `at`.
+    let prev = bytes.next().transpose()?;
+    let mut ended = state == QuoteState::Start && prev.is_some_and(|b| dialect.is_terminator(b));
+    let mut after_cr = ended && prev == Some(CR) && dialect.terminator == Terminator::CRLF;
+
+    loop {
+        let byte = bytes.next().transpose()?;
+
+        if ended {
+            // A `\n` right after a `\r` belongs to the same line terminator.
+            return Ok(match byte {
+                Some(LF) if after_cr => at + 1,
+                _ => at,
+            });
+        }
+        let Some(byte) = byte else {
+            return Ok(at);
+        };
+
+        if !state.is_quoted() && dialect.is_terminator(byte) {
+            ended = true;
+            after_cr = byte == CR && dialect.terminator == Terminator::CRLF;
+        }
+        state = state.step(byte, &dialect);
+        at += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Config;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{env, fs, process};
+
+    /// Path of a temporary file of its own for each call, like the runs of `Sorter`.
+    fn temp_path(name: &str) -> PathBuf {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let n = FILES.fetch_add(1, Ordering::Relaxed);
+        env::temp_dir().join(format!("process_csv_{name}_{}_{n}.csv", process::id()))
+    }
+
+    fn reader_from(name: &str, content: &[u8], dialect: Dialect, has_headers: bool) -> CsvReader {
+        let file_path = temp_path(&format!("par_{name}"));
+        fs::write(&file_path, content).unwrap();
+
+        CsvReader::build_from(Config {
+            file_path: file_path.to_string_lossy().into_owned(),
+            watermark: Some(4),
+            dialect,
+            has_headers,
+            workers: Some(3),
+            ..Config::default()
+        })
+        .unwrap()
+    }
+
+    fn describe(row: Result<Record, CsvError>) -> String {
+        match row {
+            Ok(record) => format!("{:?} at {}", record, record.position().unwrap()),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn same_rows_as_records() {
+        let dialects = [
+            Dialect::default(),
+            Dialect {
+                delimiter: b';',
+                quote: b'\'',
+                escape: Some(b'\\'),
+                terminator: Terminator::CRLF,
+                trim: Trim::None,
+            },
+        ];
+        let content = b"a,\"b\r\n,c\"\r\n\r\nd;'e\\'\nf'\r\r\"g\"\"\n\",h\n'i\\\\';\"\r\nj\n";
+
+        let recoveries = [Recovery::Strict, Recovery::SkipRow, Recovery::BestEffort];
+        for (i, dialect) in dialects.into_iter().enumerate() {
+            for recovery in recoveries {
+                let mut reader = reader_from(&format!("seq{i}"), content, dialect, false)
+                    .with_recovery(recovery);
+                let expected: Vec<String> = reader.records().map(describe).collect();
+                let anomalies = reader.anomalies().to_vec();
+
+                for range_size in 1..=content.len() as u64 + 1 {
+                    let mut reader = reader_from(&format!("par{i}"), content, dialect, false)
+                        .with_recovery(recovery);
+                    let rows: Vec<String> = reader
+                        .par_records_in(range_size)
+                        .unwrap()
+                        .map(describe)
+                        .collect();
+
+                    let at = format!("{recovery:?}, range size {range_size}");
+                    assert_eq!(rows, expected, "{at}");
+                    assert_eq!(reader.anomalies(), anomalies, "{at}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn after_headers_and_read_rows() {
+        let content = b"Name,Age\nBob,30\n\"Eve\nAdams\",25\nAl,7,x\nJo,9\n";
+        let mut reader = reader_from("headers", content, Dialect::default(), true);
+
+        let first = reader.records().next().unwrap().unwrap();
+        assert_eq!(first.get_by_name("Age"), Some(&b"30"[..]));
+
+        let mut rows = reader.par_records_in(5).unwrap();
+
+        let second = rows.next().unwrap().unwrap();
+        assert_eq!(second.get_by_name("Name"), Some(&b"\"Eve\nAdams\""[..]));
+        assert_eq!(
+            second.position(),
+            Some(Position {
+                record: 2,
+                line: 3,
+                byte: 16
+            })
+        );
+
+        let Some(Err(CsvError::FieldCountMismatch { position, .. })) = rows.next() else {
+            panic!("expected a field count mismatch");
+        };
+        assert_eq!((position.record, position.line, position.byte), (3, 5, 31));
+        let last = rows.next().unwrap().unwrap();
+        assert_eq!(last.get_by_name("Name"), Some(&b"Jo"[..]));
+        assert!(rows.next().is_none());
+        drop(rows);
+
+        assert!(reader.records().next().is_none());
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        let mut reader = reader_from(
+            "unterminated",
+            b"a\nb\n\"c\nd\ne\n",
+            Dialect::default(),
+            false,
+        );
+
+        let rows: Vec<Result<Record, CsvError>> = reader.par_records_in(2).unwrap().collect();
+
+        assert_eq!(rows.len(), 3);
+        let Err(CsvError::UnterminatedQuote { position }) = &rows[2] else {
+            panic!("expected an unterminated quote");
+        };
+        assert_eq!((position.record, position.line, position.byte), (2, 3, 4));
+    }
+
+    #[test]
+    fn unterminated_quote_taken_literally() {
+        let content = b"a\nb,\"c\nd\ne,f\n";
+        let expected: Vec<String> = reader_from("literal_seq", content, Dialect::default(), false)
+            .with_recovery(Recovery::BestEffort)
+            .records()
+            .map(describe)
+            .collect();
+        assert_eq!(expected.len(), 4);
+
+        for range_size in 1..=content.len() as u64 {
+            let mut reader = reader_from("literal_par", content, Dialect::default(), false)
+                .with_recovery(Recovery::BestEffort);
+            let rows: Vec<String> = reader
+                .par_records_in(range_size)
+                .unwrap()
+                .map(describe)
+                .collect();
+
+            assert_eq!(rows, expected, "range size {range_size}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "bad range 2")]
+    fn job_panics() {
+        let content = b"a\nb\nc\nd\ne\nf\n";
+        let path = temp_path("par_panic");
+        fs::write(&path, content).unwrap();
+        let source = Source {
+            path,
+            dialect: Dialect::default(),
+            recovery: Recovery::Strict,
+            watermark: 4,
+            headers: None,
+        };
+        let run = |source: &Source, job: Job| match job {
+            Job::Parse(2, ..) => panic!("bad range 2"),
+            job => run(source, job),
+        };
+
+        let mut anomalies = Vec::new();
+        let start = Position {
+            record: 0,
+            line: 1,
+            byte: 0,
+        };
+        let len = content.len() as u64;
+        let rows = ParRecords::spawn(source, &mut anomalies, start, len, 3, 2, run);
+        rows.for_each(drop);
+    }
+
+    #[test]
+    fn needs_file_path() {
+        let mut reader = CsvReader::from_reader(&b"a\nb\n"[..], Dialect::default());
+
+        assert!(matches!(reader.par_records(), Err(CsvError::Io(_))));
+    }
+}
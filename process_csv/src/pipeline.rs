@@ -0,0 +1,345 @@
+/// `Pipeline` reads the rows of a `CsvReader` on one thread and converts them on a pool of
+/// threads, handing the results back in the order of the file, see `Pipeline::run`.
+///
+/// Rows travel in batches, to keep the cost of the channels low, through bounded queues.
+/// The reader takes a credit before reading a batch and the consumer gives it back once
+/// the batch is consumed, so at most `capacity` batches are held at any time: a slow
+/// consumer makes the reader wait instead of letting it buffer the whole file in memory.
+use std::collections::HashMap;
+use std::io::Read;
+use std::num::NonZero;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::{CsvError, CsvReader, Record};
+
+/// Default number of rows sent in a message.
+pub const PIPELINE_BATCH: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pipeline {
+    workers: usize,
+    batch_size: usize,
+    capacity: usize,
+}
+
+impl Default for Pipeline {
+    /// As many workers as the available parallelism, twice as many batches in flight.
+    fn default() -> Self {
+        let workers = thread::available_parallelism().map_or(1, NonZero::get);
+        Pipeline {
+            workers,
+            batch_size: PIPELINE_BATCH,
+            capacity: workers * 2,
+        }
+    }
+}
+
+/// Rows or converted values of a batch.
+struct Batch<T> {
+    items: Vec<T>,
+    /// Error that stopped the batch, after the items done before it.
+    error: Option<CsvError>,
+}
+
+/// A batch with its rank in the file.
+type Ranked<T> = (usize, Batch<T>);
+
+/// A converted batch with its rank, or the panic of `convert` on one of its rows.
+type Converted<T> = (usize, thread::Result<Batch<T>>);
+
+impl Pipeline {
+    /// Sets the number of threads converting the rows.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets the number of rows sent in a message, `PIPELINE_BATCH` by default.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the number of batches read and not yet consumed, at most.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Reads the remaining rows of `reader`, converts them with `convert` on the workers and
+    /// calls `consume` with the results, in the order of the file, on the calling thread.
+    ///
+    /// The first error, of the reader or of `convert`, stops the pipeline once the rows
+    /// before it are consumed. So does a panic of `convert`, raised again on the calling
+    /// thread.
+    ///
+    /// # Returns
+    /// - The number of rows consumed.
+    ///
+    /// # Example
+    /// ```
+    /// use process_csv::{CellParser, CsvReader, Dialect, Pipeline};
+    ///
+    /// let content = b"Name,Age\nAlice,25\nBob,30\nCarol,40\n";
+    /// let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+    /// let parser = CellParser::default();
+    /// let mut total = 0;
+    ///
+    /// let rows = Pipeline::default()
+    ///     .with_workers(2)
+    ///     .with_batch_size(2)
+    ///     .run(
+    ///         &mut reader,
+    ///         |record| parser.parse::<u32>(record.get(1).unwrap().to_vec()),
+    ///         |age| total += age,
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!((rows, total), (3, 95));
+    /// ```
+    pub fn run<R, T, C, F>(
+        &self,
+        reader: &mut CsvReader<R>,
+        convert: C,
+        mut consume: F,
+    ) -> Result<u64, CsvError>
+    where
+        R: Read + Send,
+        T: Send,
+        C: Fn(Record) -> Result<T, CsvError> + Sync,
+        F: FnMut(T),
+    {
+        thread::scope(|scope| {
+            let (credits, tickets) = mpsc::sync_channel(self.capacity);
+            for _ in 0..self.capacity {
+                let _ = credits.send(());
+            }
+            let (rows, batches) = mpsc::sync_channel(self.capacity);
+            let (converted, done) = mpsc::sync_channel(self.capacity);
+            let batches = Arc::new(Mutex::new(batches));
+
+            let batch_size = self.batch_size;
+            scope.spawn(move || read(reader, batch_size, tickets, rows));
+            for _ in 0..self.workers {
+                let (batches, converted, convert) =
+                    (Arc::clone(&batches), converted.clone(), &convert);
+                scope.spawn(move || work(batches, converted, convert));
+            }
+            drop(converted);
+
+            // Returning drops the channels, which stops the threads before the scope
+            // waits for them.
+            let mut parsed = HashMap::new();
+            let (mut next, mut consumed) = (0, 0);
+            loop {
+                let Some(batch) = parsed.remove(&next) else {
+                    match done.recv() {
+                        Ok((i, batch)) => parsed.insert(i, batch),
+                        Err(_) => return Ok(consumed),
+                    };
+                    continue;
+                };
+
+                let Batch { items, error } = batch.unwrap_or_else(|e| panic::resume_unwind(e));
+                consumed += items.len() as u64;
+                items.into_iter().for_each(&mut consume);
+                if let Some(e) = error {
+                    return Err(e);
+                }
+
+                next += 1;
+                let _ = credits.send(());
+            }
+        })
+    }
+}
+
+/// Sends the rows of `reader` in batches of `batch_size`, each one taking a ticket.
+fn read<R: Read>(
+    reader: &mut CsvReader<R>,
+    batch_size: usize,
+    tickets: Receiver<()>,
+    rows: SyncSender<Ranked<Record>>,
+) {
+    let mut records = reader.records();
+
+    for i in 0.. {
+        if tickets.recv().is_err() {
+            return;
+        }
+
+        let mut batch = Batch {
+            items: Vec::with_capacity(batch_size),
+            error: None,
+        };
+        for row in records.by_ref().take(batch_size) {
+            match row {
+                Ok(record) => batch.items.push(record),
+                Err(e) => {
+                    batch.error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if batch.items.is_empty() && batch.error.is_none() {
+            return;
+        }
+        let last = batch.items.len() < batch_size || batch.error.is_some();
+        if rows.send((i, batch)).is_err() || last {
+            return;
+        }
+    }
+}
+
+fn work<T, C>(
+    batches: Arc<Mutex<Receiver<Ranked<Record>>>>,
+    done: SyncSender<Converted<T>>,
+    convert: &C,
+) where
+    C: Fn(Record) -> Result<T, CsvError>,
+{
+    loop {
+        let (i, batch) = match batches.lock().unwrap().recv() {
+            Ok(batch) => batch,
+            Err(_) => return,
+        };
+
+        // A panic is handed to the consumer in place of the batch, leaving it waiting for
+        // the batch would hang the pipeline.
+        let converted = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut converted = Batch {
+                items: Vec::with_capacity(batch.items.len()),
+                error: None,
+            };
+            for record in batch.items {
+                match convert(record) {
+                    Ok(item) => converted.items.push(item),
+                    Err(e) => {
+                        converted.error = Some(e);
+                        break;
+                    }
+                }
+            }
+            converted.error = converted.error.or(batch.error);
+            converted
+        }));
+
+        let failed = converted.is_err();
+        if done.send((i, converted)).is_err() || failed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CellParser, Dialect};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn numbers(n: usize) -> Vec<u8> {
+        (0..n)
+            .map(|i| format!("{i:04}\n"))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn parse(record: Record) -> Result<u32, CsvError> {
+        let parser = CellParser::default();
+        parser
+            .parse(record.get(0).unwrap().to_vec())
+            .map_err(|e| e.at(record.position().unwrap()))
+    }
+
+    #[test]
+    fn in_order() {
+        let content = numbers(1000);
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default()).without_headers();
+        let mut values = Vec::new();
+
+        let pipeline = Pipeline::default()
+            .with_workers(3)
+            .with_batch_size(7)
+            .with_capacity(2);
+        let rows = pipeline
+            .run(&mut reader, parse, |n| values.push(n))
+            .unwrap();
+
+        assert_eq!(rows, 1000);
+        assert_eq!(values, (0..1000).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn stops_at_first_error() {
+        let mut reader = CsvReader::from_reader(&b"1\n2\nthree\n4\n5\n"[..], Dialect::default())
+            .without_headers();
+        let mut values = Vec::new();
+
+        let pipeline = Pipeline::default().with_workers(2).with_batch_size(2);
+        let error = pipeline
+            .run(&mut reader, parse, |n| values.push(n))
+            .unwrap_err();
+
+        assert_eq!(values, vec![1, 2]);
+        assert!(matches!(error, CsvError::Parse(_)));
+        assert_eq!(error.position().map(|p| p.record), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "bad row 7")]
+    fn convert_panics() {
+        let content = numbers(100);
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default()).without_headers();
+
+        let pipeline = Pipeline::default().with_workers(3).with_batch_size(2);
+        let _ = pipeline.run(
+            &mut reader,
+            |record| match parse(record)? {
+                7 => panic!("bad row 7"),
+                n => Ok(n),
+            },
+            |_| {},
+        );
+    }
+
+    /// A source counting the bytes read from it.
+    struct Counting<'a>(&'a [u8], Arc<AtomicUsize>);
+
+    impl Read for Counting<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.read(buf)?;
+            self.1.fetch_add(n, Ordering::SeqCst);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn slow_consumer_holds_the_reader() {
+        let content = numbers(200);
+        let read = Arc::new(AtomicUsize::new(0));
+        let input = Counting(&content, Arc::clone(&read));
+        let mut reader = CsvReader::from_reader(input, Dialect::default())
+            .without_headers()
+            .with_watermark(8);
+
+        let pipeline = Pipeline::default()
+            .with_workers(2)
+            .with_batch_size(4)
+            .with_capacity(2);
+        let (mut consumed, mut ahead) = (0, 0);
+        pipeline
+            .run(&mut reader, parse, |_| {
+                thread::sleep(Duration::from_millis(1));
+                consumed += 5;
+                ahead = ahead.max(read.load(Ordering::SeqCst) - consumed);
+            })
+            .unwrap();
+
+        // Two batches of four 5-byte rows, and the chunks the reader holds.
+        assert!(ahead <= 2 * 4 * 5 + 2 * 8, "read {ahead} bytes ahead");
+    }
+}
@@ -1,118 +1,1183 @@
-/// `CsvReader` looks more like a byte splitter. It takes a file path and a
-/// watermark indicating the size of the chunk that is read each time.
+/// `CsvReader` looks more like a byte splitter. It takes a file path, or any `Read` source,
+/// and a watermark indicating the size of the chunk that is read each time.
 ///
 /// In order to return a byte cell whenever one is found, the `process_file` function takes
 /// a callback with a `YieldEvent` enum parameter indicating which boundary was triggered,
-/// `NewCell` (i.e., Comma (`,`)) or `NewLine` (i.e., Line Feed(`\n`)).
+/// `NewCell` (i.e., the dialect delimiter) or `NewLine` (i.e., the dialect line terminator).
 ///
-/// Quoted cells are handled correctly, allowing boundaries (such as commas or line feeds)
-/// to be included as part of the cell content without splitting the cell. The quotes remain in the cell.
+/// Quoted cells are handled correctly, allowing boundaries (such as delimiters or line feeds)
+/// to be included as part of the cell content without splitting the cell. The quotes remain in the cell,
+/// use `CellParser` to remove them. A quote only opens a quoted cell at the start of a cell,
+/// elsewhere it is malformed and handled as the `Recovery` policy says.
 ///
-/// Note that carriage returns (`\r`) are not removed by the parser and will remain at the end of each cell.
-/// If you prefer not to have `\r` characters, consider preprocessing or postprocessing the input to remove them.
+/// With the default `Terminator::CRLF`, a `\r\n` pair is a single line boundary, so carriage
+/// returns don't end up at the end of the cells.
 ///
-/// The reader processes data in chunks and invokes user-defined callbacks for further processing.
-use std::{error::Error, fs::File, io::Read, mem};
+/// The reader processes data in chunks and either invokes user-defined callbacks for further
+/// processing, hands out whole rows through the `records` iterator, or lends rows borrowing
+/// its internal buffer through `next_row`.
+use std::io::{self, Read, Seek, SeekFrom};
+use std::iter::Take;
+use std::{fs, fs::File, num::NonZero, ops::Range, path::PathBuf, sync::Arc, thread};
 
-use crate::{COMMA, Config, LF, QUOTES};
+use encoding_rs::UTF_8;
+use memmap2::Mmap;
 
-pub struct CsvReader {
-    file: File,
+use crate::encoding::{self, Encoding};
+use crate::parallel::{RANGE_SIZE, Source};
+use crate::record::Span;
+use crate::splitter::{BoundaryEvent, Splitter};
+use crate::{
+    Anomaly, BorrowedRecord, CellParser, Compression, Config, CsvError, Dialect, Headers, Index,
+    ParRecords, Position, Record, Recovery,
+};
+
+pub struct CsvReader<R = Box<dyn Read + Send>> {
+    input: R,
+    path: Option<PathBuf>, // file the input was opened from, needed by `par_records`
     watermark: usize,
+    workers: usize,
+    dialect: Dialect,
+    has_headers: bool,
+    headers: Option<Arc<Headers>>,
+    headers_read: bool,
+    splitter: Splitter,
+    buf: Buffer,
+    spill: Vec<u8>, // cells of a row that started in a previous chunk
+    spans: Vec<Span>,
+    rows: Vec<usize>, // end of each complete row in `spans`
+    positions: Vec<Position>,
+    malformed: Vec<Option<Anomaly>>, // first anomaly of each complete row
+    row: usize,                      // next row to hand out
+    unterminated: Option<Position>,
+    eof: bool,
+    recovery: Recovery,
+    anomaly: Option<Anomaly>, // first anomaly of the row being split
+    anomalies: Vec<Anomaly>,
 }
 impl CsvReader {
-    pub fn build_from(config: Config) -> Result<Self, Box<dyn Error>> {
-        let file = File::open(config.file_path)?;
+    /// Opens `config.file_path`, or reads the standard input when the path is `-`.
+    ///
+    /// Gzip and zstd inputs, recognized by their first bytes or a `.gz` or `.zst`
+    /// extension, are decompressed as they are read.
+    ///
+    /// With `mmap` set in the config, an uncompressed file is mapped in memory and split
+    /// in place rather than copied chunk by chunk into a buffer, so rows spanning two
+    /// chunks don't need copying either. The standard input and compressed files are
+    /// read as usual.
+    pub fn build_from(config: Config) -> Result<Self, CsvError> {
+        let (input, path): (Box<dyn Read + Send>, _) = match config.file_path.as_str() {
+            "-" => (Box::new(io::stdin()), None),
+            file_path => {
+                let file = File::open(file_path)?;
+                let path = PathBuf::from(file_path);
+
+                if config.mmap {
+                    // SAFETY: like with any mapped file, the file must not be modified
+                    // while the reader is alive.
+                    let map = unsafe { Mmap::map(&file)? };
+                    #[cfg(unix)]
+                    map.advise(memmap2::Advice::Sequential)?;
+                    let magic = &map[..map.len().min(4)];
+                    // Anything but plain UTF-8 goes through `encoding::decode` below.
+                    let plain = config.encoding == UTF_8
+                        && !config.lossy
+                        && Encoding::for_bom(magic).is_none();
+
+                    if plain && Compression::detect(magic, Some(&path)) == Compression::None {
+                        let mut reader =
+                            CsvReader::from_reader(input_of(io::empty()), config.dialect);
+                        reader.buf = Buffer::Mapped { map, end: 0 };
+                        return Ok(reader.configure(&config, Some(path)));
+                    }
+                }
+
+                (input_of(file), Some(path))
+            }
+        };
+
+        let (compression, input) = Compression::decompress(input, path.as_deref())?;
+        let decoded = encoding::decode(input, config.encoding, config.lossy)?;
+        // Workers of `par_records` seek into the file, which compressed or transcoded
+        // files don't allow.
+        let path = path.filter(|_| compression == Compression::None && !decoded.transcoded);
+
+        let mut reader = CsvReader::from_reader(decoded.input, config.dialect);
+        if !decoded.transcoded {
+            // Keeps byte positions as offsets in the file.
+            reader.splitter = Splitter::starting_at(config.dialect, decoded.bom as u64);
+        }
+        Ok(reader.configure(&config, path))
+    }
+
+    /// Moves the reader to `row`, counted from 0 with the header row excluded, like in
+    /// `slice`. The rows before it aren't split, the reader jumps to the closest entry of
+    /// `index` and splits the rows from there.
+    ///
+    /// Like `par_records`, this needs a reader built from the path of an uncompressed
+    /// file that isn't transcoded. The header row is read first, if it wasn't already.
+    ///
+    /// # Returns
+    /// - `Ok(())`, the reader being at the end of the file when `row` is past the last one.
+    /// - `Err(CsvError::Io)` when the reader has no file path, see `is_seekable`.
+    /// - `Err(CsvError::InvalidArgument)` when the length of the file isn't the one the index
+    ///   was built from, or when the index was built with another dialect, header setting or
+    ///   recovery policy than the reader's.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use process_csv::{Config, CsvReader, Index};
+    /// # let config = || Config::build_from(["", "sample.csv"].map(String::from).into_iter()).unwrap();
+    /// let index = Index::build(&mut CsvReader::build_from(config()).unwrap(), 1000).unwrap();
+    /// index.save("sample.csv").unwrap();
+    ///
+    /// let mut reader = CsvReader::build_from(config()).unwrap();
+    /// reader.seek_row(&index, 5_000_000).unwrap();
+    /// let row = reader.next_row();
+    /// ```
+    pub fn seek_row(&mut self, index: &Index, row: u64) -> Result<(), CsvError> {
+        let Some(path) = self.path.clone() else {
+            let message = "seek_row needs a reader opened from an uncompressed file";
+            return Err(io::Error::new(io::ErrorKind::Unsupported, message).into());
+        };
+        let len = match &self.buf {
+            Buffer::Owned(_) => fs::metadata(&path)?.len(),
+            Buffer::Mapped { map, .. } => map.len() as u64,
+        };
+        if len != index.file_len() {
+            let message = "The index doesn't match the file, it was built before it changed";
+            return Err(CsvError::InvalidArgument(message.to_string()));
+        }
+        if index.dialect() != self.dialect
+            || index.has_headers() != self.has_headers
+            || index.recovery() != self.recovery
+        {
+            let message = "The index was built with another dialect, header or recovery setting";
+            return Err(CsvError::InvalidArgument(message.to_string()));
+        }
+        self.read_headers()?;
+
+        self.spill.clear();
+        self.spans.clear();
+        self.rows.clear();
+        self.positions.clear();
+        self.malformed.clear();
+        self.row = 0;
+        self.unterminated = None;
+        self.anomaly = None;
+        let Some(start) = index.locate(row) else {
+            self.eof = true;
+            return Ok(());
+        };
+        self.eof = false;
+
+        match &mut self.buf {
+            Buffer::Owned(buf) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(start.byte))?;
+                self.input = input_of(file);
+                buf.clear();
+                self.splitter = Splitter::resuming(self.dialect, start, 0);
+            }
+            Buffer::Mapped { end, .. } => {
+                *end = start.byte as usize;
+                self.splitter = Splitter::resuming(self.dialect, start, *end);
+            }
+        }
+
+        for _ in 0..row % index.every() {
+            if let Some(Err(e)) = self.advance() {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the rows in `range`, counted like in `seek_row`, which
+    /// moves the reader to the start of the range.
+    pub fn slice_rows(
+        &mut self,
+        index: &Index,
+        range: Range<u64>,
+    ) -> Result<Take<Records<'_>>, CsvError> {
+        self.seek_row(index, range.start)?;
+        let len = range.end.saturating_sub(range.start);
+        Ok(self.records().take(len.try_into().unwrap_or(usize::MAX)))
+    }
 
-        let watermark = config.watermark.unwrap_or(1024 * 8); // 8KB
+    fn configure(mut self, config: &Config, path: Option<PathBuf>) -> Self {
+        self.path = path;
+        self.has_headers = config.has_headers;
+        self.recovery = config.recovery;
+        if let Some(watermark) = config.watermark {
+            self.watermark = watermark;
+        }
+        if let Some(workers) = config.workers {
+            self.workers = workers;
+        }
+        self
+    }
+}
+
+fn input_of(input: impl Read + Send + 'static) -> Box<dyn Read + Send> {
+    Box::new(input)
+}
+
+fn would_block(error: &CsvError) -> bool {
+    matches!(error, CsvError::Io(e) if e.kind() == io::ErrorKind::WouldBlock)
+}
 
-        Ok(CsvReader { file, watermark })
+/// Bytes the splitter works on: a chunk buffer refilled from the input, or the whole
+/// file mapped in memory, of which the first `end` bytes were split so far.
+enum Buffer {
+    Owned(Vec<u8>),
+    Mapped { map: Mmap, end: usize },
+}
+
+impl Buffer {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Buffer::Owned(buf) => buf,
+            Buffer::Mapped { map, end } => &map[..*end],
+        }
     }
 }
 
-type ByteCell = Vec<u8>;
-enum BoundaryEvent<'a> {
-    NewCell(&'a [u8]),
-    NewLine,
+impl CsvReader<File> {
+    /// Reader over the rows of `source` from byte `start` on, which must be the start of a row.
+    /// Positions are counted from there, the first row being record 0 on line 1.
+    pub(crate) fn resume_at(source: &Source, start: u64) -> Result<Self, CsvError> {
+        let mut file = File::open(&source.path)?;
+        file.seek(SeekFrom::Start(start))?;
+
+        let mut reader =
+            CsvReader::from_reader(file, source.dialect).with_watermark(source.watermark);
+        reader.splitter = Splitter::starting_at(source.dialect, start);
+        reader.recovery = source.recovery;
+        reader.has_headers = source.headers.is_some();
+        reader.headers = source.headers.clone();
+        reader.headers_read = true;
+
+        Ok(reader)
+    }
 }
+
+impl<R: Read> CsvReader<R> {
+    /// Builds a reader over any byte source, such as the standard input, a byte slice or
+    /// a decompressor. The first row is taken as the header row, see `without_headers`.
+    ///
+    /// # Example
+    /// ```
+    /// # use process_csv::{CsvReader, Dialect};
+    /// let mut reader = CsvReader::from_reader(&b"Name,Age\nBob,30\n"[..], Dialect::default());
+    ///
+    /// let bob = reader.records().next().unwrap().unwrap();
+    /// assert_eq!(bob.get_by_name("Age"), Some(&b"30"[..]));
+    /// ```
+    pub fn from_reader(input: R, dialect: Dialect) -> Self {
+        CsvReader {
+            input,
+            path: None,
+            watermark: 1024 * 8, // 8KB
+            workers: thread::available_parallelism().map_or(1, NonZero::get),
+            dialect,
+            has_headers: true,
+            headers: None,
+            headers_read: false,
+            splitter: Splitter::new(dialect),
+            buf: Buffer::Owned(Vec::new()),
+            spill: Vec::new(),
+            spans: Vec::new(),
+            rows: Vec::new(),
+            positions: Vec::new(),
+            malformed: Vec::new(),
+            row: 0,
+            unterminated: None,
+            eof: false,
+            recovery: Recovery::Strict,
+            anomaly: None,
+            anomalies: Vec::new(),
+        }
+    }
+
+    /// Takes the first row as a record rather than as the header row.
+    pub fn without_headers(mut self) -> Self {
+        self.has_headers = false;
+        self
+    }
+
+    /// Sets the size of the chunks read from the input.
+    pub fn with_watermark(mut self, watermark: usize) -> Self {
+        self.watermark = watermark;
+        self
+    }
+
+    /// Sets what happens to the rows holding a malformed quote, `Recovery::Strict` by
+    /// default.
+    pub fn with_recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Whether the first row is read as the header row, see `without_headers`.
+    pub fn has_headers(&self) -> bool {
+        self.has_headers
+    }
+
+    /// Whether the reader can jump to a row with `seek_row` or hand its rows to the
+    /// workers of `par_records`, which takes a reader built from the path of an
+    /// uncompressed file that isn't transcoded.
+    pub fn is_seekable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Malformed quotes found so far in the rows skipped or kept by the recovery policy,
+    /// in the order of the file. Always empty with `Recovery::Strict`.
+    ///
+    /// # Example
+    /// ```
+    /// use process_csv::{CsvReader, Dialect, Recovery};
+    ///
+    /// let content = b"Item,Size\nTV,55\" screen\nRadio,small\n";
+    /// let mut reader = CsvReader::from_reader(&content[..], Dialect::default())
+    ///     .with_recovery(Recovery::SkipRow);
+    ///
+    /// let rows: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+    /// assert_eq!(rows.len(), 1);
+    /// assert_eq!(reader.anomalies()[0].to_string(), "Stray quote on line 2, byte 15, in record 1");
+    /// ```
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+
+    /// Returns the header row, reading it if no row was read yet.
+    ///
+    /// # Returns
+    /// - `Ok(None)` when the config has `has_headers` unset.
+    /// - `Err(CsvError::NoHeaders)` when the file is empty.
+    pub fn headers(&mut self) -> Result<Option<&Headers>, CsvError> {
+        self.read_headers()?;
+
+        match (&self.headers, self.has_headers) {
+            (Some(headers), _) => Ok(Some(headers)),
+            (None, true) => Err(CsvError::NoHeaders),
+            (None, false) => Ok(None),
+        }
+    }
+
+    fn read_headers(&mut self) -> Result<(), CsvError> {
+        if !self.has_headers || self.headers_read {
+            return Ok(());
+        }
+
+        let header = self.advance().transpose();
+        if let Err(e) = &header
+            && would_block(e)
+        {
+            return header.map(|_| ());
+        }
+        self.headers_read = true;
+        let Some((range, position)) = header? else {
+            return Ok(());
+        };
+
+        let row = BorrowedRecord::new(
+            self.buf.bytes(),
+            &self.spill,
+            &self.spans[range],
+            None,
+            position,
+        );
+        let headers = Headers::build_from(row, &CellParser::from(self.dialect))?;
+        self.headers = Some(Arc::new(headers));
+
+        Ok(())
+    }
+}
+
+type ByteCell = Vec<u8>;
 /// Enum representing yielded events when processing the CSV file.
 pub enum YieldEvent {
     NewCell(ByteCell),
     NewLine,
 }
 
-impl CsvReader {
+impl<R: Read> CsvReader<R> {
     /// Processes the CSV file in chunks and triggers a callback `on_yield` for each cell or line encountered.
     ///
+    /// Every row is yielded, the header row included, whatever `has_headers` says: a
+    /// `NewCell` for each of its cells, then a `NewLine` if a line terminator ends it. So at
+    /// the end of the input, the last row is followed by a `NewLine` only when the file ends
+    /// with a line terminator, and no empty row is yielded after it.
+    ///
     /// # Parameters
     /// - `on_yield: F`: A function that handles `YieldEvent` occurrences.
     ///
     /// # Returns
-    /// - `Result<(), Box<dyn Error>>`
-    pub fn process_file<F>(mut self, mut on_yield: F) -> Result<(), Box<dyn Error>>
+    /// - `Result<(), CsvError>`
+    pub fn process_file<F>(mut self, mut on_yield: F) -> Result<(), CsvError>
     where
         F: FnMut(YieldEvent),
     {
-        let mut chunk = vec![0; self.watermark];
-        let mut unp_bytes: Vec<u8> = Vec::new(); // unprocessed_bytes
+        // A row is only known to be the last, and so maybe without a line terminator, at
+        // the end of the input, so its `NewLine` waits for the next row.
+        let mut last = None;
 
-        loop {
-            let n = self.file.read(&mut chunk)?;
-            if n == 0 {
-                break;
+        while let Some(row) = self.advance() {
+            let (range, position) = row?;
+            if last.replace(position).is_some() {
+                on_yield(YieldEvent::NewLine);
+            }
+            for span in &self.spans[range] {
+                on_yield(YieldEvent::NewCell(
+                    span.slice(self.buf.bytes(), &self.spill).to_vec(),
+                ));
             }
+        }
 
-            chunk.truncate(n);
+        if let Some(last) = last
+            && !(self.splitter.trailing() && last.record + 1 == self.end().record)
+        {
+            on_yield(YieldEvent::NewLine);
+        }
 
-            if !unp_bytes.is_empty() {
-                unp_bytes.append(&mut chunk);
-                chunk = mem::take(&mut unp_bytes);
-            }
+        Ok(())
+    }
+
+    /// Returns an iterator over the rows of the file. Rows are read lazily, one chunk
+    /// at a time, so stopping the iteration early stops reading the file.
+    ///
+    /// Like `process_file`, a line terminator at the end of the file doesn't produce an
+    /// empty last row. When the config has `has_headers` set, the header row is skipped and
+    /// rows with a different number of cells than the header are yielded as errors.
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records { reader: self }
+    }
+
+    /// Returns an iterator over the rows left in the file, parsed on a pool of threads.
+    ///
+    /// The file is split into byte ranges that the workers parse on their own. Each range
+    /// starts at the first row that begins inside it, found by tracking whether its first
+    /// byte is inside quotes, so quoted cells with line breaks don't throw the split off.
+    /// Rows are yielded in the file order, with the same positions and errors as `records`,
+    /// and the malformed quotes the workers find are added to `anomalies` as they are.
+    ///
+    /// The number of threads comes from the `workers` config, it defaults to the available
+    /// parallelism. The reader itself is left at the end of the file. A panic of a worker is
+    /// raised again by the iterator.
+    ///
+    /// Workers open the file on their own, so this needs a reader built from the path of an
+    /// uncompressed file.
+    ///
+    /// # Returns
+    /// - `Err(CsvError)` when the reader has no file path, or when the header row or the
+    ///   file size can't be read.
+    pub fn par_records(&mut self) -> Result<ParRecords<'_>, CsvError> {
+        self.par_records_in(RANGE_SIZE)
+    }
+
+    pub(crate) fn par_records_in(&mut self, range_size: u64) -> Result<ParRecords<'_>, CsvError> {
+        let Some(path) = self.path.clone() else {
+            let message = "par_records needs a reader opened from an uncompressed file";
+            return Err(io::Error::new(io::ErrorKind::Unsupported, message).into());
+        };
+        self.read_headers()?;
+
+        let start = match self.positions.get(self.row) {
+            Some(&position) => position,
+            None => self.splitter.row_start(),
+        };
+        let len = fs::metadata(&path)?.len();
+
+        self.eof = true;
+        self.unterminated = None;
+        self.row = self.rows.len();
+
+        let source = Source {
+            path,
+            dialect: self.dialect,
+            recovery: self.recovery,
+            watermark: self.watermark,
+            headers: self.headers.clone(),
+        };
+
+        Ok(ParRecords::new(
+            source,
+            &mut self.anomalies,
+            start,
+            len,
+            self.workers,
+            range_size,
+        ))
+    }
+
+    /// Lends the next row of the file without copying its cells.
+    ///
+    /// The cells borrow the chunk buffer of the reader, so the row has to be dropped
+    /// before asking for the next one. Cells of a row that spans two chunks are the
+    /// exception: the ones read with the previous chunk are copied aside before the
+    /// buffer is refilled.
+    ///
+    /// Headers are handled like in `records`. When the input fails with
+    /// `io::ErrorKind::WouldBlock`, like a non-blocking socket with nothing to read yet, so
+    /// does `next_row`, and calling it again picks up where it stopped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use process_csv::{Config, CsvReader};
+    /// # let config = Config::build_from(["", "sample.csv"].map(String::from).into_iter()).unwrap();
+    /// let mut reader = CsvReader::build_from(config).unwrap();
+    ///
+    /// while let Some(row) = reader.next_row() {
+    ///     let row = row.unwrap();
+    ///     println!("{} cells, first has {} bytes", row.len(), row.get(0).unwrap().len());
+    /// }
+    /// ```
+    pub fn next_row(&mut self) -> Option<Result<BorrowedRecord<'_>, CsvError>> {
+        self.lend_row(true)
+    }
+
+    /// Like `next_row`, lending rows whatever their number of cells.
+    pub(crate) fn next_unchecked_row(&mut self) -> Option<Result<BorrowedRecord<'_>, CsvError>> {
+        self.lend_row(false)
+    }
+
+    fn lend_row(&mut self, check_len: bool) -> Option<Result<BorrowedRecord<'_>, CsvError>> {
+        if let Err(e) = self.read_headers() {
+            return Some(Err(e));
+        }
 
-            let remaining = Self::split_chunk(&mut chunk, |boundary| match boundary {
-                BoundaryEvent::NewCell(c) => on_yield(YieldEvent::NewCell(c.to_vec())),
-                BoundaryEvent::NewLine => on_yield(YieldEvent::NewLine),
-            });
+        let (range, position) = match self.advance()? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e)),
+        };
 
-            unp_bytes = Vec::from(remaining);
+        if check_len
+            && let Some(headers) = &self.headers
+            && headers.len() != range.len()
+        {
+            return Some(Err(CsvError::FieldCountMismatch {
+                position,
+                expected: headers.len(),
+                found: range.len(),
+            }));
         }
 
-        on_yield(YieldEvent::NewCell(unp_bytes));
-        return Ok(());
+        Some(Ok(BorrowedRecord::new(
+            self.buf.bytes(),
+            &self.spill,
+            &self.spans[range],
+            self.headers.as_ref(),
+            position,
+        )))
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn input_mut(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    /// Position where the next row starts, the end of the input once it is exhausted.
+    pub(crate) fn end(&self) -> Position {
+        self.splitter.row_start()
     }
 
-    /// Splits a chunk of CSV data into individual cells and lines.
+    /// Moves to the next row, filling the buffer when needed. Rows holding a malformed
+    /// quote are errors or skipped, depending on the recovery policy.
     ///
-    /// # Parameters
-    /// - `chunk: &'a mut [u8]`: The mutable byte slice containing CSV data.
-    /// - `on_boundary: F`: A function handling boundary events.
+    /// # Returns
+    /// - The range of the row cells in `spans` and the position of the row.
+    fn advance(&mut self) -> Option<Result<(Range<usize>, Position), CsvError>> {
+        loop {
+            while self.row == self.rows.len() {
+                match self.fill() {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let position = self.unterminated.take()?;
+                        return Some(Err(CsvError::UnterminatedQuote { position }));
+                    }
+                    // A non-blocking input has nothing to read yet, try again later.
+                    Err(e) if would_block(&e) => return Some(Err(e)),
+                    Err(e) => {
+                        self.eof = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+
+            let start = match self.row {
+                0 => 0,
+                i => self.rows[i - 1],
+            };
+            let end = self.rows[self.row];
+            let position = self.positions[self.row];
+            let malformed = self.malformed[self.row];
+            self.row += 1;
+
+            match (malformed, self.recovery) {
+                (Some(anomaly), Recovery::Strict) => {
+                    return Some(Err(CsvError::Malformed(anomaly)));
+                }
+                (Some(_), Recovery::SkipRow) => continue,
+                _ => return Some(Ok((start..end, position))),
+            }
+        }
+    }
+
+    /// Reads the next chunk of the file and splits it. Only the new bytes are scanned,
+    /// the splitter picks up where it stopped with the previous chunk.
+    ///
+    /// When the end of the file is reached, the bytes still unprocessed are flushed as
+    /// the last cell of the last row, unless they are inside an unclosed quote.
     ///
     /// # Returns
-    /// - The remaining unprocessed portion of the chunk.
-    fn split_chunk<'a, F>(chunk: &'a mut [u8], mut on_boundary: F) -> &'a [u8]
-    where
-        F: FnMut(BoundaryEvent<'a>),
-    {
-        let mut between_quotes = false;
-        let mut j = 0;
+    /// - `Ok(false)` once the file is exhausted, `Ok(true)` otherwise.
+    fn fill(&mut self) -> Result<bool, CsvError> {
+        if self.eof {
+            return Ok(false);
+        }
 
-        for (i, byte) in chunk.iter().enumerate() {
-            if *byte == QUOTES {
-                between_quotes = !between_quotes;
+        let first = self.rows.last().copied().unwrap_or(0);
+        match &self.buf {
+            // The row left incomplete by the previous chunk still points into the bytes
+            // that are about to be dropped from `buf`, so its cells are copied to `spill`.
+            Buffer::Owned(buf) => {
+                let mut spill = Vec::new();
+                let spans: Vec<Span> = self
+                    .spans
+                    .drain(first..)
+                    .map(|span| {
+                        let start = spill.len();
+                        spill.extend_from_slice(span.slice(buf, &self.spill));
+                        Span::Spill(start..spill.len())
+                    })
+                    .collect();
+
+                self.spans = spans;
+                self.spill = spill;
             }
-            if between_quotes {
-                continue;
+            // The map doesn't move, the incomplete row keeps pointing into it.
+            Buffer::Mapped { .. } => {
+                self.spans.drain(..first);
             }
+        }
+        self.rows.clear();
+        self.positions.clear();
+        self.malformed.clear();
+        self.row = 0;
+
+        let n = match &mut self.buf {
+            Buffer::Owned(buf) => {
+                let consumed = self.splitter.consumed();
+                buf.drain(..consumed);
+                self.splitter.shift(consumed);
+
+                let len = buf.len();
+                buf.resize(len + self.watermark, 0);
 
-            if *byte == COMMA || *byte == LF {
-                on_boundary(BoundaryEvent::NewCell(&chunk[j..i]));
-                j = i + 1;
+                let n = match self.input.read(&mut buf[len..]) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        buf.truncate(len);
+                        return Err(e.into());
+                    }
+                };
+                buf.truncate(len + n);
+                n
             }
-            if *byte == LF {
-                on_boundary(BoundaryEvent::NewLine);
+            Buffer::Mapped { map, end } => {
+                let n = self.watermark.min(map.len() - *end);
+                *end += n;
+                n
             }
+        };
+
+        let strict = self.recovery == Recovery::Strict;
+        let (spans, rows, positions) = (&mut self.spans, &mut self.rows, &mut self.positions);
+        let (malformed, anomaly, anomalies) =
+            (&mut self.malformed, &mut self.anomaly, &mut self.anomalies);
+        let mut on_boundary = |boundary| match boundary {
+            BoundaryEvent::NewCell(c) => spans.push(Span::Buffer(c)),
+            BoundaryEvent::NewLine(position) => {
+                rows.push(spans.len());
+                positions.push(position);
+                malformed.push(anomaly.take());
+            }
+            BoundaryEvent::Anomaly(found) => {
+                anomaly.get_or_insert(found);
+                if !strict {
+                    anomalies.push(found);
+                }
+            }
+        };
+
+        if n == 0 {
+            // Unless strict, a quote left open takes the rest of the file back: it is
+            // taken literally and the bytes after it split again.
+            loop {
+                self.unterminated = self.splitter.finish(self.buf.bytes(), &mut on_boundary);
+                if strict || self.unterminated.is_none() {
+                    break;
+                }
+                self.splitter.unquote(&mut on_boundary);
+            }
+            self.eof = true;
+        } else {
+            self.splitter.split(self.buf.bytes(), on_boundary);
+        }
+
+        Ok(true)
+    }
+}
+
+/// Iterator over the rows of a `CsvReader`, see `CsvReader::records`.
+pub struct Records<'r, R = Box<dyn Read + Send>> {
+    reader: &'r mut CsvReader<R>,
+}
+
+impl<R: Read> Iterator for Records<'_, R> {
+    type Item = Result<Record, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader
+            .next_row()
+            .map(|row| row.map(|row| row.to_record()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Terminator, Trim};
+    use proptest::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{env, fs, process};
+
+    /// Path of a temporary file of its own for each call, like the runs of `Sorter`.
+    fn temp_path(name: &str) -> PathBuf {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let n = FILES.fetch_add(1, Ordering::Relaxed);
+        env::temp_dir().join(format!("process_csv_{name}_{}_{n}.csv", process::id()))
+    }
+
+    fn reader_from(content: &[u8], watermark: usize) -> CsvReader<&[u8]> {
+        CsvReader::from_reader(content, Dialect::default())
+            .without_headers()
+            .with_watermark(watermark)
+    }
+
+    /// Reads every row with its position, or the error that stopped the reader.
+    fn read_all(content: &[u8], watermark: usize, dialect: Dialect) -> Vec<Result<String, String>> {
+        CsvReader::from_reader(content, dialect)
+            .without_headers()
+            .with_watermark(watermark)
+            .records()
+            .map(|record| match record {
+                Ok(record) => Ok(format!("{:?} at {}", record, record.position().unwrap())),
+                Err(e) => Err(e.to_string()),
+            })
+            .collect()
+    }
+
+    fn dialects() -> impl Strategy<Value = Dialect> {
+        prop_oneof![
+            Just(Dialect::default()),
+            Just(Dialect {
+                escape: Some(b'\\'),
+                ..Dialect::default()
+            }),
+            Just(Dialect {
+                delimiter: b';',
+                quote: b'\'',
+                terminator: Terminator::Any(b'\n'),
+                escape: None,
+                trim: Trim::None,
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn chunk_size_does_not_change_output(
+            content in prop::collection::vec(
+                prop::sample::select(b"ab,;\"'\\\r\n".to_vec()),
+                0..256,
+            ),
+            watermark in prop_oneof![1..=16usize, 1..=1024 * 1024usize],
+            dialect in dialects(),
+        ) {
+            let whole = read_all(&content, content.len() + 1, dialect);
+            let chunked = read_all(&content, watermark, dialect);
+
+            prop_assert_eq!(whole, chunked);
         }
+    }
+
+    #[test]
+    fn records_across_chunks() {
+        let mut reader = reader_from(b"a,\"b\nb\"\r\nc,d\r\n", 3);
+
+        let records: Vec<Record> = reader.records().map(Result::unwrap).collect();
+
+        assert_eq!(
+            records,
+            vec![
+                Record::from(vec![b"a".to_vec(), b"\"b\nb\"".to_vec()]),
+                Record::from(vec![b"c".to_vec(), b"d".to_vec()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_early_exit() {
+        let mut reader = reader_from(b"a\nb\nc", 1);
+
+        let first = reader.records().next().unwrap().unwrap();
+        assert_eq!(first.get(0), Some(&b"a"[..]));
+
+        let rest: Vec<Record> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rest.len(), 2);
+        assert_eq!(rest[1].get(0), Some(&b"c"[..]));
+    }
+
+    #[test]
+    fn borrowed_rows_spanning_chunks() {
+        let mut reader = reader_from(b"name,age\nalice,25\nbob,30", 4);
+        let mut rows = Vec::new();
+
+        while let Some(row) = reader.next_row() {
+            let row = row.unwrap();
+            rows.push(
+                row.iter()
+                    .map(|c| String::from_utf8_lossy(c).into_owned())
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        assert_eq!(rows, vec![["name", "age"], ["alice", "25"], ["bob", "30"]]);
+        assert!(reader.next_row().is_none());
+    }
+
+    #[test]
+    fn process_file_events() {
+        let events = |content, recovery| {
+            let mut events = String::new();
+            reader_from(content, 2)
+                .with_recovery(recovery)
+                .process_file(|event| match event {
+                    YieldEvent::NewCell(c) => events.push_str(&String::from_utf8(c).unwrap()),
+                    YieldEvent::NewLine => events.push('|'),
+                })
+                .unwrap();
+            events
+        };
+
+        assert_eq!(events(b"a,b\nc\n", Recovery::Strict), "ab|c|");
+        assert_eq!(events(b"a,b\r\nc", Recovery::Strict), "ab|c");
+        assert_eq!(events(b"a,\"b\nc\"", Recovery::Strict), "a\"b\nc\"");
+        assert_eq!(events(b"", Recovery::Strict), "");
+        assert_eq!(events(b"a,b\nc\"d", Recovery::SkipRow), "ab|");
+    }
+
+    #[test]
+    fn header_lookup() {
+        let mut reader = reader_from(b"Name,Age\nalice,25\nbob\ncarol,30", 4);
+        reader.has_headers = true;
+
+        assert_eq!(reader.headers().unwrap().unwrap().position("Age"), Some(1));
+
+        let mut records = reader.records();
+        let alice = records.next().unwrap().unwrap();
+        assert_eq!(alice.get_by_name("Age"), Some(&b"25"[..]));
+        assert_eq!(alice.get_by_name("Email"), None);
+
+        assert!(matches!(
+            records.next(),
+            Some(Err(CsvError::FieldCountMismatch {
+                expected: 2,
+                found: 1,
+                ..
+            }))
+        ));
+        assert_eq!(
+            records.next().unwrap().unwrap().get_by_name("Name"),
+            Some(&b"carol"[..])
+        );
+    }
+
+    #[test]
+    fn header_errors() {
+        let mut reader = reader_from(b"a,b,a\n1,2,3", 16);
+        reader.has_headers = true;
+        assert!(matches!(
+            reader.headers(),
+            Err(CsvError::DuplicateHeader { column: 2, .. })
+        ));
+
+        let mut reader = reader_from(b"a,,c\n1,2,3", 16);
+        reader.has_headers = true;
+        assert!(matches!(
+            reader.headers(),
+            Err(CsvError::MissingHeader { column: 1 })
+        ));
+
+        let mut reader = reader_from(b"", 16);
+        reader.has_headers = true;
+        assert!(matches!(reader.headers(), Err(CsvError::NoHeaders)));
+        assert!(reader.records().next().is_none());
+    }
+
+    #[test]
+    fn positions_across_chunks() {
+        let mut reader = reader_from(b"a,\"multi\r\nline\"\r\nb,c\r\n\r\nd,e", 3);
+        let positions: Vec<Position> = reader
+            .records()
+            .map(|r| r.unwrap().position().unwrap())
+            .collect();
+
+        let expected = [(0, 1, 0), (1, 3, 17), (2, 4, 22), (3, 5, 24)];
+        assert_eq!(positions.len(), expected.len());
+        for (position, (record, line, byte)) in positions.iter().zip(expected) {
+            assert_eq!(
+                (position.record, position.line, position.byte),
+                (record, line, byte)
+            );
+        }
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        let mut reader = reader_from(b"a,b\nc,\"d,e\nf\n", 4);
+        let mut records = reader.records();
+
+        assert!(records.next().unwrap().is_ok());
+        let Some(Err(CsvError::UnterminatedQuote { position })) = records.next() else {
+            panic!("expected an unterminated quote");
+        };
+        assert_eq!((position.record, position.line, position.byte), (1, 2, 4));
+        assert!(records.next().is_none());
+    }
+
+    /// An input that has nothing to read every other call.
+    struct NonBlocking<'a>(&'a [u8], bool);
+
+    impl Read for NonBlocking<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1 = !self.1;
+            match self.1 {
+                true => Err(io::ErrorKind::WouldBlock.into()),
+                false => self.0.read(buf),
+            }
+        }
+    }
+
+    #[test]
+    fn resumes_after_would_block() {
+        let content = b"Name,Note\nAlice,\"two\nlines\"\nBob,x\n";
+        let mut reader = CsvReader::from_reader(NonBlocking(content, false), Dialect::default())
+            .with_watermark(3);
+
+        let mut rows = Vec::new();
+        while let Some(row) = reader.next_row() {
+            match row {
+                Ok(row) => rows.push(row.to_record()),
+                Err(e) => assert!(would_block(&e), "{e}"),
+            }
+        }
+
+        let expected: Vec<Record> = CsvReader::from_reader(&content[..], Dialect::default())
+            .records()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rows, expected);
+        assert_eq!(rows[0].get_by_name("Note"), Some(&b"\"two\nlines\""[..]));
+    }
+
+    #[test]
+    fn recovery_policies() {
+        let content = b"a,5\" b\nc,d\ne,\"f\ng\n";
+        let read = |recovery| {
+            let mut reader = reader_from(content, 4).with_recovery(recovery);
+            let rows: Vec<Result<Vec<u8>, String>> = reader
+                .records()
+                .map(|row| row.map(|r| r.iter().collect::<Vec<_>>().join(&b'|')))
+                .map(|row| row.map_err(|e| e.to_string()))
+                .collect();
+            (rows, reader.anomalies().len())
+        };
+
+        let (rows, anomalies) = read(Recovery::Strict);
+        assert_eq!(
+            rows,
+            vec![
+                Err("Malformed row: Stray quote on line 1, byte 3, in record 0".to_string()),
+                Ok(b"c|d".to_vec()),
+                Err(
+                    "Quoted cell is never closed, record starts at record 2, line 3, byte 11"
+                        .to_string()
+                ),
+            ]
+        );
+        assert_eq!(anomalies, 0);
+
+        let (rows, anomalies) = read(Recovery::SkipRow);
+        assert_eq!(rows, vec![Ok(b"c|d".to_vec()), Ok(b"g".to_vec())]);
+        assert_eq!(anomalies, 2);
+
+        let (rows, anomalies) = read(Recovery::BestEffort);
+        assert_eq!(
+            rows,
+            vec![
+                Ok(b"a|5\" b".to_vec()),
+                Ok(b"c|d".to_vec()),
+                Ok(b"e|\"f".to_vec()),
+                Ok(b"g".to_vec()),
+            ]
+        );
+        assert_eq!(anomalies, 2);
+    }
+
+    #[test]
+    fn mapped_file_like_read() {
+        let file_path = temp_path("mapped");
+
+        for content in [&b"a,\"b\r\nc\"\r\nd,e\n\"f"[..], b"a,b\r\n", b""] {
+            fs::write(&file_path, content).unwrap();
+
+            for watermark in [1, 3, 64] {
+                let mut reader = CsvReader::build_from(Config {
+                    file_path: file_path.to_string_lossy().into_owned(),
+                    watermark: Some(watermark),
+                    has_headers: false,
+                    mmap: true,
+                    ..Config::default()
+                })
+                .unwrap();
+                assert!(matches!(reader.buf, Buffer::Mapped { .. }));
+
+                let rows: Vec<Result<String, String>> = reader
+                    .records()
+                    .map(|record| match record {
+                        Ok(record) => Ok(format!("{:?} at {}", record, record.position().unwrap())),
+                        Err(e) => Err(e.to_string()),
+                    })
+                    .collect();
+
+                assert_eq!(rows, read_all(content, watermark, Dialect::default()));
+                assert!(reader.spill.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn encoded_files() {
+        let file_path = temp_path("encoded");
+        let config = |encoding, mmap| Config {
+            file_path: file_path.to_string_lossy().into_owned(),
+            watermark: Some(4),
+            mmap,
+            encoding,
+            ..Config::default()
+        };
+
+        fs::write(&file_path, b"\xef\xbb\xbfName,Age\nBob,30\n").unwrap();
+        for mmap in [false, true] {
+            let mut reader = CsvReader::build_from(config(UTF_8, mmap)).unwrap();
+            assert_eq!(reader.headers().unwrap().unwrap().get(0), Some("Name"));
+
+            let bob = reader.records().next().unwrap().unwrap();
+            assert_eq!(bob.position().unwrap().byte, 12);
+            assert!(reader.path.is_some());
+        }
+
+        fs::write(&file_path, b"Name,City\nZo\xeb,K\xf6ln\n").unwrap();
+        let latin1 = Encoding::for_label(b"latin1").unwrap();
+        let mut reader = CsvReader::build_from(config(latin1, true)).unwrap();
+        let zoe = reader.records().next().unwrap().unwrap();
+        assert_eq!(zoe.get_by_name("City"), Some("Köln".as_bytes()));
+        assert!(reader.path.is_none());
+    }
+
+    #[test]
+    fn seek_rows() {
+        let file_path = temp_path("indexed");
+        let content = b"\xef\xbb\xbfName,Note\r\nAlice,\"two\r\nlines\"\r\nBob,x\r\nCarol,\"y\"\"\"\r\nDave,z";
+        fs::write(&file_path, content).unwrap();
+        let config = |mmap| Config {
+            file_path: file_path.to_string_lossy().into_owned(),
+            watermark: Some(3),
+            mmap,
+            ..Config::default()
+        };
+        let describe = |record: Result<Record, CsvError>| {
+            let record = record.unwrap();
+            let cells: Vec<&[u8]> = record.iter().collect();
+            format!("{:?} at {}", cells, record.position().unwrap())
+        };
+
+        let mut reader = CsvReader::build_from(config(false)).unwrap();
+        let index = Index::build(&mut reader, 3).unwrap();
+        let mut reader = CsvReader::build_from(config(false)).unwrap();
+        let all: Vec<String> = reader.records().map(describe).collect();
+
+        for mmap in [false, true] {
+            let mut reader = CsvReader::build_from(config(mmap)).unwrap();
+            for row in [4, 1, 3, 0, 2] {
+                reader.seek_row(&index, row).unwrap();
+                let rows: Vec<String> = reader.records().map(describe).collect();
+                assert_eq!(rows, all[row as usize..], "from row {row}");
+            }
+
+            let rows: Vec<String> = reader
+                .slice_rows(&index, 1..3)
+                .unwrap()
+                .map(describe)
+                .collect();
+            assert_eq!(rows, all[1..3]);
+            assert_eq!(reader.headers().unwrap().unwrap().get(1), Some("Note"));
+        }
+
+        let mut reader = CsvReader::build_from(config(false))
+            .unwrap()
+            .without_headers();
+        assert!(matches!(
+            reader.seek_row(&index, 1),
+            Err(CsvError::InvalidArgument(_))
+        ));
+        let mut reader = CsvReader::build_from(Config {
+            dialect: Dialect {
+                delimiter: b';',
+                ..Dialect::default()
+            },
+            ..config(false)
+        })
+        .unwrap();
+        assert!(matches!(
+            reader.seek_row(&index, 1),
+            Err(CsvError::InvalidArgument(_))
+        ));
+        let mut reader = CsvReader::build_from(config(false))
+            .unwrap()
+            .with_recovery(Recovery::BestEffort);
+        assert!(matches!(
+            reader.seek_row(&index, 1),
+            Err(CsvError::InvalidArgument(_))
+        ));
 
-        &chunk[j..]
+        fs::write(&file_path, b"Name,Note\n").unwrap();
+        let mut reader = CsvReader::build_from(config(false)).unwrap();
+        assert!(reader.is_seekable());
+        assert!(matches!(
+            reader.seek_row(&index, 1),
+            Err(CsvError::InvalidArgument(_))
+        ));
     }
 }
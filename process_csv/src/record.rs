@@ -0,0 +1,163 @@
+use std::{ops::Range, sync::Arc, vec};
+
+use crate::{Headers, Position};
+
+type ByteCell = Vec<u8>;
+
+/// A whole CSV row. Cells are kept as raw bytes, exactly as `CsvReader` split them,
+/// use `CellParser` to turn them into values.
+///
+/// Two records are equal when their cells are, wherever they come from.
+#[derive(Debug, Clone, Default)]
+pub struct Record {
+    cells: Vec<ByteCell>,
+    headers: Option<Arc<Headers>>,
+    position: Option<Position>,
+}
+impl From<Vec<ByteCell>> for Record {
+    fn from(cells: Vec<ByteCell>) -> Self {
+        Record {
+            cells,
+            headers: None,
+            position: None,
+        }
+    }
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.cells == other.cells
+    }
+}
+impl Eq for Record {}
+
+impl Record {
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        self.cells.get(i).map(|c| c.as_slice())
+    }
+
+    /// Returns the cell under the column called `name`, when the reader has headers.
+    pub fn get_by_name(&self, name: &str) -> Option<&[u8]> {
+        self.get(self.headers.as_ref()?.position(name)?)
+    }
+
+    pub fn headers(&self) -> Option<&Headers> {
+        self.headers.as_deref()
+    }
+
+    /// Where the record starts in the file, when it was read by `CsvReader`.
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+
+    pub(crate) fn rebase(&mut self, base: Position) {
+        self.position = self.position.map(|p| p.rebase(base));
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.cells.iter().map(|c| c.as_slice())
+    }
+}
+
+impl IntoIterator for Record {
+    type Item = ByteCell;
+    type IntoIter = vec::IntoIter<ByteCell>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cells.into_iter()
+    }
+}
+
+/// Where the bytes of a borrowed cell live.
+#[derive(Debug, Clone)]
+pub(crate) enum Span {
+    Buffer(Range<usize>),
+    Spill(Range<usize>),
+}
+impl Span {
+    pub(crate) fn slice<'a>(&self, buf: &'a [u8], spill: &'a [u8]) -> &'a [u8] {
+        match self {
+            Span::Buffer(r) => &buf[r.clone()],
+            Span::Spill(r) => &spill[r.clone()],
+        }
+    }
+}
+
+/// A CSV row whose cells borrow the internal buffer of `CsvReader`,
+/// see `CsvReader::next_row`.
+#[derive(Clone, Copy)]
+pub struct BorrowedRecord<'r> {
+    buf: &'r [u8],
+    spill: &'r [u8],
+    spans: &'r [Span],
+    headers: Option<&'r Arc<Headers>>,
+    position: Position,
+}
+
+impl<'r> BorrowedRecord<'r> {
+    pub(crate) fn new(
+        buf: &'r [u8],
+        spill: &'r [u8],
+        spans: &'r [Span],
+        headers: Option<&'r Arc<Headers>>,
+        position: Position,
+    ) -> Self {
+        BorrowedRecord {
+            buf,
+            spill,
+            spans,
+            headers,
+            position,
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Option<&'r [u8]> {
+        self.spans.get(i).map(|s| s.slice(self.buf, self.spill))
+    }
+
+    /// Returns the cell under the column called `name`, when the reader has headers.
+    pub fn get_by_name(&self, name: &str) -> Option<&'r [u8]> {
+        self.get(self.headers?.position(name)?)
+    }
+
+    pub fn headers(&self) -> Option<&'r Headers> {
+        self.headers.map(|h| h.as_ref())
+    }
+
+    /// Where the record starts in the file.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'r [u8]> + use<'r> {
+        let BorrowedRecord {
+            buf, spill, spans, ..
+        } = *self;
+        spans.iter().map(move |s| s.slice(buf, spill))
+    }
+
+    /// Copies the cells into an owned `Record`.
+    pub fn to_record(&self) -> Record {
+        Record {
+            cells: self.iter().map(|c| c.to_vec()).collect(),
+            headers: self.headers.cloned(),
+            position: Some(self.position),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Position;
+
+/// What `CsvReader` does with a row holding a malformed quote, see `Anomaly`.
+///
+/// The splitter takes such quotes literally whatever the policy, as many spreadsheet tools
+/// do, so a stray quote never swallows the rest of the file: the policy only decides what
+/// becomes of the row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// The row is an error, `CsvError::Malformed`, and an unclosed quote at the end of the
+    /// file is `CsvError::UnterminatedQuote`.
+    #[default]
+    Strict,
+    /// The row is left out and its anomalies reported.
+    SkipRow,
+    /// The row is kept with its quotes taken literally and its anomalies reported.
+    BestEffort,
+}
+
+impl FromStr for Recovery {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Recovery::Strict),
+            "skip-row" => Ok(Recovery::SkipRow),
+            "best-effort" => Ok(Recovery::BestEffort),
+            _ => Err("Expected one of strict, skip-row or best-effort"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// A quote in the middle of an unquoted cell, e.g. `5" screen`. Quotes only open a
+    /// quoted cell at its start, after leading whitespace when the dialect trims it.
+    StrayQuote,
+    /// A quote opening a cell that is never closed before the end of the file.
+    UnterminatedQuote,
+}
+
+/// A malformed quote found while splitting a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    /// Position of the row holding the quote.
+    pub position: Position,
+    /// 1-based line of the quote.
+    pub line: u64,
+    /// 0-based offset of the quote.
+    pub byte: u64,
+}
+
+impl Anomaly {
+    /// Moves an anomaly found in a range of the input, see `Position::rebase`.
+    pub(crate) fn rebase(self, base: Position) -> Anomaly {
+        Anomaly {
+            position: self.position.rebase(base),
+            line: base.line + self.line - 1,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            AnomalyKind::StrayQuote => "Stray quote",
+            AnomalyKind::UnterminatedQuote => "Unterminated quote",
+        };
+        write!(
+            f,
+            "{kind} on line {}, byte {}, in record {}",
+            self.line, self.byte, self.position.record
+        )
+    }
+}
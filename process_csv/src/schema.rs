@@ -0,0 +1,301 @@
+use std::fmt;
+use std::io::Read;
+
+use crate::{CellParser, CsvError, CsvReader};
+
+/// Format of the dates of a `ColumnType::Date` column.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Type of the values of a column, from the most to the least specific. A column takes
+/// the most specific type `CellParser` reads all its values as: integers and floats mixed
+/// in a column make it a float column, `0`, `1` and `yes` a bool one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    /// Read with `CellParser::to_float`, so with its decimal separator.
+    Float,
+    /// Read with `CellParser::to_bool`, e.g. `true`, `no` or `1`.
+    Bool,
+    /// `YYYY-MM-DD`.
+    Date,
+    String,
+}
+
+/// Set of the types a value can be read as, one bit per `ColumnType` but `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Candidates(u8);
+
+impl Candidates {
+    const TYPES: [ColumnType; 4] = [
+        ColumnType::Integer,
+        ColumnType::Float,
+        ColumnType::Bool,
+        ColumnType::Date,
+    ];
+
+    fn of(parser: &CellParser, cell: &[u8]) -> Candidates {
+        // Keeps `inf` or `NaN` out of float columns.
+        let numeric = cell.iter().any(u8::is_ascii_digit);
+        let read = |kind| match kind {
+            ColumnType::Integer => parser.parse::<i64>(cell.to_vec()).is_ok(),
+            ColumnType::Float => numeric && parser.to_float(cell.to_vec()).is_ok(),
+            ColumnType::Bool => parser.to_bool(cell.to_vec()).is_ok(),
+            ColumnType::Date => parser.to_date(cell.to_vec(), DATE_FORMAT).is_ok(),
+            ColumnType::String => true,
+        };
+
+        let bits = Self::TYPES.iter().enumerate();
+        Candidates(bits.fold(0, |set, (i, &kind)| set | (read(kind) as u8) << i))
+    }
+
+    fn and(self, other: Candidates) -> Candidates {
+        Candidates(self.0 & other.0)
+    }
+
+    /// The most specific of the types.
+    fn kind(self) -> ColumnType {
+        let mut bits = Self::TYPES.iter().enumerate();
+        bits.find(|(i, _)| self.0 & 1 << i != 0)
+            .map_or(ColumnType::String, |(_, &kind)| kind)
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// Header of the column, when the reader has headers.
+    pub name: Option<String>,
+    /// `String` when the column has no values at all.
+    pub kind: ColumnType,
+    /// Whether some rows have the cell null or missing.
+    pub nullable: bool,
+    /// Length in characters of the longest value.
+    pub max_width: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub columns: Vec<Column>,
+    /// Number of rows the schema was inferred from.
+    pub rows: usize,
+}
+
+/// Infers the type of each column from the next `rows` rows of `reader`.
+///
+/// Cells are read with a `CellParser` of the dialect of the reader, its null tokens, such
+/// as an empty cell or `NA`, counting as nulls rather than values.
+///
+/// # Returns
+/// - `Err(CsvError)` for the first row that can't be read, e.g. when it has a different
+///   number of cells than the header.
+pub fn infer_schema<R: Read>(reader: &mut CsvReader<R>, rows: usize) -> Result<Schema, CsvError> {
+    let parser = CellParser::from(reader.dialect());
+    let mut columns: Vec<(Option<Candidates>, Column)> = match reader.headers()? {
+        Some(headers) => headers
+            .iter()
+            .map(|name| (None, new_column(Some(name.to_string()))))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut sampled = 0;
+    while sampled < rows
+        && let Some(row) = reader.next_row()
+    {
+        let row = row?;
+        sampled += 1;
+
+        if columns.len() < row.len() {
+            // Columns missing from the rows before are null there.
+            let nullable = sampled > 1;
+            columns.resize_with(row.len(), || {
+                let mut column = new_column(None);
+                column.nullable = nullable;
+                (None, column)
+            });
+        }
+
+        for (i, (kind, column)) in columns.iter_mut().enumerate() {
+            let Some(raw) = row.get(i) else {
+                column.nullable = true;
+                continue;
+            };
+            let value = parser
+                .to_string(raw.to_vec())
+                .map_err(|e| e.at(row.position()).in_cell(i, column.name.as_deref(), raw))?;
+
+            if parser.is_null(raw)? {
+                column.nullable = true;
+                continue;
+            }
+
+            let candidates = Candidates::of(&parser, raw);
+            *kind = Some(kind.map_or(candidates, |kind| kind.and(candidates)));
+            column.max_width = column.max_width.max(value.chars().count());
+        }
+    }
+
+    let columns = columns
+        .into_iter()
+        .map(|(kind, column)| Column {
+            kind: kind.map_or(ColumnType::String, Candidates::kind),
+            ..column
+        })
+        .collect();
+
+    Ok(Schema {
+        columns,
+        rows: sampled,
+    })
+}
+
+fn new_column(name: Option<String>) -> Column {
+    Column {
+        name,
+        kind: ColumnType::String,
+        nullable: false,
+        max_width: 0,
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| column.name.clone().unwrap_or_else(|| i.to_string()))
+            .collect();
+        let width = names
+            .iter()
+            .map(|name| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let width = width.max("column".len());
+
+        writeln!(
+            f,
+            "{:<width$}  {:<8}  {:<8}  max width",
+            "column", "type", "nullable"
+        )?;
+        for (name, column) in names.iter().zip(&self.columns) {
+            let nullable = if column.nullable { "yes" } else { "no" };
+            writeln!(
+                f,
+                "{name:<width$}  {:<8}  {nullable:<8}  {}",
+                column.kind, column.max_width
+            )?;
+        }
+        write!(f, "({} rows sampled)", self.rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    #[test]
+    fn column_types() {
+        let content = b"id,price,active,since,name,note\n\
+            1,10,true,2024-01-31,Alice,\n\
+            2,10.5,FALSE,2023-12-01,\"Bob, Jr.\",x\n\
+            3,,false,2024-02-29,7,\n";
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+
+        let schema = infer_schema(&mut reader, 100).unwrap();
+
+        let kinds: Vec<(ColumnType, bool, usize)> = schema
+            .columns
+            .iter()
+            .map(|c| (c.kind, c.nullable, c.max_width))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ColumnType::Integer, false, 1),
+                (ColumnType::Float, true, 4),
+                (ColumnType::Bool, false, 5),
+                (ColumnType::Date, false, 10),
+                (ColumnType::String, false, 8),
+                (ColumnType::String, true, 1),
+            ]
+        );
+        assert_eq!(schema.columns[4].name.as_deref(), Some("name"));
+        assert_eq!(schema.rows, 3);
+    }
+
+    #[test]
+    fn sample_without_headers() {
+        let content = b"1,a\n2\n3,b,4.5\nx,y,z\n";
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default()).without_headers();
+
+        let schema = infer_schema(&mut reader, 3).unwrap();
+
+        let kinds: Vec<(Option<String>, ColumnType, bool)> = schema
+            .columns
+            .into_iter()
+            .map(|c| (c.name, c.kind, c.nullable))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (None, ColumnType::Integer, false),
+                (None, ColumnType::String, true),
+                (None, ColumnType::Float, true),
+            ]
+        );
+        assert_eq!(schema.rows, 3);
+    }
+
+    #[test]
+    fn parser_tokens() {
+        let content = b"flag,score,note\n\
+            yes,1,NA\n\
+            0,NULL,NULL\n\
+            1,2,\n";
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+
+        let schema = infer_schema(&mut reader, 100).unwrap();
+
+        let kinds: Vec<(ColumnType, bool)> = schema
+            .columns
+            .iter()
+            .map(|c| (c.kind, c.nullable))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ColumnType::Bool, false),
+                (ColumnType::Integer, true),
+                (ColumnType::String, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let kind = |cells: &[&str]| {
+            let parser = CellParser::default();
+            let candidates = cells.iter().map(|c| Candidates::of(&parser, c.as_bytes()));
+            candidates.reduce(Candidates::and).unwrap().kind()
+        };
+
+        assert_eq!(kind(&["2024-02-29", "2023-12-01"]), ColumnType::Date);
+        assert_eq!(kind(&["2024-02-29", "2023-02-29"]), ColumnType::String);
+        assert_eq!(kind(&["2024-13-01"]), ColumnType::String);
+        assert_eq!(kind(&["24-01-2024"]), ColumnType::String);
+    }
+}
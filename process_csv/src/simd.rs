@@ -0,0 +1,91 @@
+/// Bitmask helpers for the splitter, which looks at its input 64 bytes at a time: bit `i` of
+/// a mask stands for byte `i` of the block.
+///
+/// On x86_64 the masks are built with SSE2 comparisons, 16 bytes at a time. Other targets
+/// use a scalar loop producing the same masks.
+pub(crate) const BLOCK: usize = 64;
+
+/// Masks of the bytes of `block` equal to each of the `needles`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn eq_masks<const N: usize>(block: &[u8], needles: [u8; N]) -> [u64; N] {
+    use std::arch::x86_64::{
+        __m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_set1_epi8,
+    };
+
+    assert!(block.len() >= BLOCK);
+
+    // SAFETY: SSE2 is part of the x86_64 baseline, and the unaligned loads read the first
+    // 64 bytes of `block`, which is at least that long.
+    unsafe {
+        let lanes =
+            [0, 16, 32, 48].map(|i| _mm_loadu_si128(block.as_ptr().add(i) as *const __m128i));
+
+        needles.map(|needle| {
+            let needle = _mm_set1_epi8(needle as i8);
+            lanes.iter().enumerate().fold(0, |mask, (i, lane)| {
+                let bits = _mm_movemask_epi8(_mm_cmpeq_epi8(*lane, needle)) as u16;
+                mask | (bits as u64) << (i * 16)
+            })
+        })
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn eq_masks<const N: usize>(block: &[u8], needles: [u8; N]) -> [u64; N] {
+    eq_masks_scalar(block, needles)
+}
+
+#[cfg(any(test, not(target_arch = "x86_64")))]
+fn eq_masks_scalar<const N: usize>(block: &[u8], needles: [u8; N]) -> [u64; N] {
+    needles.map(|needle| {
+        block[..BLOCK]
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (i, &byte)| mask | ((byte == needle) as u64) << i)
+    })
+}
+
+/// Sets every bit to the parity of the bits up to it. Applied to the quotes of a block, it
+/// marks the bytes inside quotes, opening quotes included and closing quotes excluded.
+pub(crate) fn prefix_xor(mut mask: u64) -> u64 {
+    mask ^= mask << 1;
+    mask ^= mask << 2;
+    mask ^= mask << 4;
+    mask ^= mask << 8;
+    mask ^= mask << 16;
+    mask ^= mask << 32;
+    mask
+}
+
+/// Mask of the bits below bit `n`.
+pub(crate) fn below(n: usize) -> u64 {
+    match n {
+        BLOCK.. => u64::MAX,
+        n => (1 << n) - 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn masks_match_scalar() {
+        let block: Vec<u8> = (0..BLOCK as u8)
+            .map(|i| b"a,\"\r\n\xff"[i as usize % 6])
+            .collect();
+        let needles = [b',', b'"', b'\r', b'\n', 0xff, b'z'];
+
+        assert_eq!(eq_masks(&block, needles), eq_masks_scalar(&block, needles));
+        assert_eq!(eq_masks(&block, [b'a'])[0] & 0b1111111, 0b1000001);
+    }
+
+    #[test]
+    fn quoted_bytes() {
+        // a"b,c"d
+        let quotes = 0b0100010;
+        assert_eq!(prefix_xor(quotes), 0b0011110);
+        assert_eq!(below(3), 0b111);
+        assert_eq!(below(BLOCK), u64::MAX);
+    }
+}
@@ -0,0 +1,510 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::{env, mem};
+
+use crate::headers::column_of;
+use crate::{BorrowedRecord, CellParser, CsvError, CsvReader, CsvWriter, Dialect, Recovery};
+
+const SORT_MEMORY: usize = 1024 * 1024 * 64; // 64MB
+const MERGE_FAN_IN: usize = 64;
+
+/// Counts the runs written by the process, to name their files.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts the rows of a file by one or more columns, with an external merge sort: rows are
+/// sorted in batches that fit in the memory budget, written to temporary files and merged
+/// back. Files that fit in memory are sorted without temporary files. At most `fan_in`
+/// files are merged at once, in several passes when there are more, to keep the number of
+/// open files low.
+///
+/// Values are compared as numbers when both are numbers, numbers coming before the other
+/// values, and as strings otherwise. The sort is stable, rows with the same keys keep the
+/// order they have in the file.
+///
+/// # Example
+/// ```
+/// use process_csv::{CsvReader, CsvWriter, Dialect, Sorter};
+///
+/// let content = b"Name,Age\nBob,30\nAlice,9\nCarol,30\n";
+/// let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+/// let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+///
+/// Sorter::new(&["Age"]).reversed().sort(&mut reader, &mut out).unwrap();
+/// assert_eq!(out.into_inner().unwrap(), b"Name,Age\r\nBob,30\r\nCarol,30\r\nAlice,9\r\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sorter {
+    keys: Vec<String>,
+    reverse: bool,
+    memory: usize,
+    fan_in: usize,
+    temp_dir: PathBuf,
+}
+
+impl Sorter {
+    /// Sorter by the given columns, named after the header or given by 0-based index.
+    /// Rows with the same value in the first column are sorted by the second, and so on.
+    pub fn new(keys: &[&str]) -> Self {
+        Sorter {
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            reverse: false,
+            memory: SORT_MEMORY,
+            fan_in: MERGE_FAN_IN,
+            temp_dir: env::temp_dir(),
+        }
+    }
+
+    /// Sorts in descending order.
+    pub fn reversed(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    /// Sets the approximate number of bytes of rows held in memory, 64MB by default.
+    pub fn with_memory(mut self, bytes: usize) -> Self {
+        self.memory = bytes;
+        self
+    }
+
+    /// Sets the number of temporary files merged at once, 64 by default and 2 at least.
+    pub fn with_fan_in(mut self, files: usize) -> Self {
+        self.fan_in = files.max(2);
+        self
+    }
+
+    /// Sets the directory of the temporary files, `env::temp_dir()` by default.
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    /// Writes the remaining rows of `reader` to `out` in order, after the header row.
+    /// Cells are written as they are in the file, so `out` should use the dialect of the
+    /// reader.
+    ///
+    /// # Returns
+    /// - The number of rows written, the header row excluded.
+    pub fn sort<R: Read, W: Write>(
+        &self,
+        reader: &mut CsvReader<R>,
+        out: &mut CsvWriter<W>,
+    ) -> Result<u64, CsvError> {
+        let dialect = reader.dialect();
+        let headers = reader.headers()?;
+        let columns = self
+            .keys
+            .iter()
+            .map(|name| column_of(headers, name))
+            .collect::<Result<Vec<usize>, CsvError>>()?;
+        if let Some(headers) = headers {
+            out.write_record(headers.iter())?;
+        }
+
+        let keys = Keys {
+            columns,
+            parser: CellParser::from(dialect),
+            reverse: self.reverse,
+        };
+        let mut batch = Vec::new();
+        let mut size = 0;
+        let mut runs = Vec::new();
+        let mut rows = 0;
+
+        while let Some(row) = reader.next_row() {
+            let row = row?;
+            let entry = keys.entry(&row)?;
+            size += entry.size();
+            batch.push(entry);
+            rows += 1;
+
+            if size >= self.memory {
+                runs.push(self.write_run(&keys, mem::take(&mut batch), dialect)?);
+                size = 0;
+            }
+        }
+
+        keys.sort(&mut batch);
+        if runs.is_empty() {
+            for entry in batch {
+                out.write_raw_record(entry.cells.iter().map(|c| c.as_slice()))?;
+            }
+            return Ok(rows);
+        }
+        if !batch.is_empty() {
+            runs.push(self.write_run(&keys, batch, dialect)?);
+        }
+
+        // Consecutive runs are merged together, so the runs stay in the order of the file.
+        while runs.len() > self.fan_in {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(self.fan_in));
+            let mut pass = runs.into_iter().peekable();
+            while pass.peek().is_some() {
+                let group: Vec<Run> = pass.by_ref().take(self.fan_in).collect();
+                merged.push(self.merge_runs(&keys, group, dialect)?);
+            }
+            runs = merged;
+        }
+
+        merge(&keys, &runs, dialect, out)?;
+        Ok(rows)
+    }
+
+    /// Creates an empty run in the temporary directory, with a writer to its file.
+    fn new_run(&self, dialect: Dialect) -> Result<(Run, CsvWriter<File>), CsvError> {
+        let name = format!(
+            "process_csv_sort_{}_{}.csv",
+            process::id(),
+            RUNS.fetch_add(1, AtomicOrdering::Relaxed)
+        );
+        let path = self.temp_dir.join(name);
+        let writer = CsvWriter::from_writer(File::create(&path)?, dialect);
+        // The run owns the file from here, to remove it whatever happens next.
+        Ok((Run { path }, writer))
+    }
+
+    fn write_run(
+        &self,
+        keys: &Keys,
+        mut batch: Vec<Entry>,
+        dialect: Dialect,
+    ) -> Result<Run, CsvError> {
+        keys.sort(&mut batch);
+
+        let (run, mut writer) = self.new_run(dialect)?;
+        for entry in &batch {
+            writer.write_raw_record(entry.cells.iter().map(|c| c.as_slice()))?;
+        }
+        writer.flush()?;
+        Ok(run)
+    }
+
+    /// Merges `runs` into a new run, removing their files.
+    fn merge_runs(&self, keys: &Keys, runs: Vec<Run>, dialect: Dialect) -> Result<Run, CsvError> {
+        if runs.len() == 1 {
+            return Ok(runs.into_iter().next().expect("one run"));
+        }
+
+        let (run, mut writer) = self.new_run(dialect)?;
+        merge(keys, &runs, dialect, &mut writer)?;
+        writer.flush()?;
+        Ok(run)
+    }
+}
+
+/// Extracts and compares the sort keys of the rows.
+pub(crate) struct Keys {
+    pub(crate) columns: Vec<usize>,
+    pub(crate) parser: CellParser,
+    pub(crate) reverse: bool,
+}
+
+impl Keys {
+    pub(crate) fn entry(&self, row: &BorrowedRecord) -> Result<Entry, CsvError> {
+        let mut key = Vec::with_capacity(self.columns.len());
+        for &i in &self.columns {
+            let raw = row.get(i).unwrap_or_default();
+            let value = self
+                .parser
+                .to_string(raw.to_vec())
+                .map_err(|e| e.at(row.position()).in_cell(i, None, raw))?;
+
+            key.push(match value.parse::<f64>() {
+                Ok(n) => Value::Number(n),
+                Err(_) => Value::Text(value),
+            });
+        }
+
+        Ok(Entry {
+            key,
+            cells: row.iter().map(|c| c.to_vec()).collect(),
+        })
+    }
+
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        let ordering = a.key.cmp(&b.key);
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    fn sort(&self, batch: &mut [Entry]) {
+        batch.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+/// Value of a key cell. Equal values have the same hash, which lets `join` hash keys
+/// compared like `Sorter` compares them.
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    Number(f64),
+    Text(String),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Number(n) => n.to_bits().hash(state),
+            Value::Text(text) => text.hash(state),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            (Value::Number(_), Value::Text(_)) => Ordering::Less,
+            (Value::Text(_), Value::Number(_)) => Ordering::Greater,
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+        }
+    }
+}
+
+pub(crate) struct Entry {
+    pub(crate) key: Vec<Value>,
+    pub(crate) cells: Vec<Vec<u8>>,
+}
+
+impl Entry {
+    /// Approximate number of bytes the entry takes in memory.
+    fn size(&self) -> usize {
+        let text = |value: &Value| match value {
+            Value::Text(text) => text.len(),
+            Value::Number(_) => 0,
+        };
+        let cells: usize = self.cells.iter().map(|c| c.len()).sum();
+        let keys: usize = self.key.iter().map(text).sum();
+
+        mem::size_of::<Entry>()
+            + cells
+            + self.cells.len() * mem::size_of::<Vec<u8>>()
+            + keys
+            + self.key.len() * mem::size_of::<Value>()
+    }
+}
+
+/// A sorted batch of rows written to a temporary file, removed when the run is dropped.
+struct Run {
+    path: PathBuf,
+}
+
+impl Run {
+    fn open(&self, dialect: Dialect) -> Result<CsvReader<File>, CsvError> {
+        // Cells are written as they were read, malformed quotes included, and the reader
+        // already reported them: they are read back as they are.
+        let input = File::open(&self.path)?;
+        Ok(CsvReader::from_reader(input, dialect)
+            .without_headers()
+            .with_recovery(Recovery::BestEffort))
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The next row of a run, ordered by key and then by run, which keeps the sort stable
+/// as the runs follow the order of the file.
+struct Head<'k> {
+    keys: &'k Keys,
+    entry: Entry,
+    run: usize,
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.keys
+            .compare(&self.entry, &other.entry)
+            .then(self.run.cmp(&other.run))
+    }
+}
+
+/// Merges the sorted runs into `out`, holding one row per run in memory.
+fn merge<W: Write>(
+    keys: &Keys,
+    runs: &[Run],
+    dialect: Dialect,
+    out: &mut CsvWriter<W>,
+) -> Result<(), CsvError> {
+    let mut readers = runs
+        .iter()
+        .map(|run| run.open(dialect))
+        .collect::<Result<Vec<_>, CsvError>>()?;
+    let next = |run: usize, readers: &mut [CsvReader<File>]| -> Result<Option<Head>, CsvError> {
+        match readers[run].next_row() {
+            Some(row) => Ok(Some(Head {
+                keys,
+                entry: keys.entry(&row?)?,
+                run,
+            })),
+            None => Ok(None),
+        }
+    };
+
+    let mut heads = BinaryHeap::with_capacity(readers.len());
+    for run in 0..readers.len() {
+        if let Some(head) = next(run, &mut readers)? {
+            heads.push(Reverse(head));
+        }
+    }
+
+    while let Some(Reverse(head)) = heads.pop() {
+        out.write_raw_record(head.entry.cells.iter().map(|c| c.as_slice()))?;
+        if let Some(head) = next(head.run, &mut readers)? {
+            heads.push(Reverse(head));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    const USERS: &[u8] = b"Name,Age,Country\n\
+        Alice,25,USA\n\
+        \"Smith,\nBob\",31,UK\n\
+        Carol,9,\"USA\"\n\
+        Dave,,France\n\
+        Eve,31,France\n";
+
+    fn sorted(sorter: &Sorter, content: &[u8], has_headers: bool) -> String {
+        let mut reader = CsvReader::from_reader(content, Dialect::default());
+        if !has_headers {
+            reader = reader.without_headers();
+        }
+        let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+
+        sorter.sort(&mut reader, &mut out).unwrap();
+        String::from_utf8(out.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn in_memory() {
+        assert_eq!(
+            sorted(&Sorter::new(&["Age"]), USERS, true),
+            "Name,Age,Country\r\n\
+             Carol,9,\"USA\"\r\n\
+             Alice,25,USA\r\n\
+             \"Smith,\nBob\",31,UK\r\n\
+             Eve,31,France\r\n\
+             Dave,,France\r\n"
+        );
+        assert_eq!(
+            sorted(&Sorter::new(&["Country", "0"]).reversed(), USERS, true),
+            "Name,Age,Country\r\n\
+             Carol,9,\"USA\"\r\n\
+             Alice,25,USA\r\n\
+             \"Smith,\nBob\",31,UK\r\n\
+             Eve,31,France\r\n\
+             Dave,,France\r\n"
+        );
+    }
+
+    #[test]
+    fn merged_runs() {
+        let temp_dir = env::temp_dir().join("process_csv_sort_runs");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let sorter = Sorter::new(&["Age"])
+            .with_memory(1)
+            .with_temp_dir(&temp_dir);
+
+        for fan_in in [2, 3, 64] {
+            assert_eq!(
+                sorted(&sorter.clone().with_fan_in(fan_in), USERS, true),
+                sorted(&Sorter::new(&["Age"]), USERS, true),
+                "fan-in {fan_in}"
+            );
+            assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn merged_stray_quotes() {
+        let content = b"Item,Size\nTV,55\" screen\nRadio,small\nLamp,\"a\"b\n";
+        let sort = |sorter: Sorter| {
+            let mut reader = CsvReader::from_reader(&content[..], Dialect::default())
+                .with_recovery(Recovery::BestEffort);
+            let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+            sorter.sort(&mut reader, &mut out).unwrap();
+            (out.into_inner().unwrap(), reader.anomalies().len())
+        };
+
+        let (merged, anomalies) = sort(Sorter::new(&["Item"]).with_memory(1));
+        assert_eq!(
+            merged,
+            b"Item,Size\r\nLamp,\"a\"b\r\nRadio,small\r\nTV,55\" screen\r\n"
+        );
+        assert_eq!((merged, anomalies), sort(Sorter::new(&["Item"])));
+    }
+
+    #[test]
+    fn unknown_column() {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+        let e = Sorter::new(&["City"])
+            .sort(&mut reader, &mut out)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "No column named \"City\"");
+    }
+
+    proptest! {
+        #[test]
+        fn runs_like_memory(
+            rows in prop::collection::vec((0u8..5, "[a-c]{0,2}"), 0..40),
+            memory in 1usize..2000,
+            fan_in in 2usize..5,
+        ) {
+            let mut content = Vec::new();
+            for (i, (number, text)) in rows.iter().enumerate() {
+                content.extend(format!("{number},{text},{i}\n").into_bytes());
+            }
+            let sorter = Sorter::new(&["1", "0"]);
+
+            prop_assert_eq!(
+                sorted(&sorter.clone().with_memory(memory).with_fan_in(fan_in), &content, false),
+                sorted(&sorter, &content, false)
+            );
+        }
+    }
+}
@@ -0,0 +1,582 @@
+/// `Splitter` is the state machine behind `CsvReader`. It finds the cell and line
+/// boundaries of a buffer that grows one chunk at a time, keeping its state between
+/// chunks so every byte is looked at only once, however large a quoted cell gets.
+///
+/// The caller owns the buffer: it appends chunks, calls `split` to scan the new bytes and
+/// may drop the bytes before `consumed` once it is done with the cells they hold, telling
+/// the splitter with `shift`.
+use std::mem;
+use std::ops::Range;
+
+use crate::simd::{self, BLOCK};
+use crate::{Anomaly, AnomalyKind, CR, Dialect, LF, Position, Terminator, Trim};
+
+pub(crate) enum BoundaryEvent {
+    /// Raw bytes of a cell, as a range of the buffer.
+    NewCell(Range<usize>),
+    /// End of a row, with the position where it started.
+    NewLine(Position),
+    /// A malformed quote in the current row, taken literally.
+    Anomaly(Anomaly),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Outside quotes, at the start of a cell or past some of its bytes.
+    Unquoted,
+    Quoted,
+    /// Right after the escape byte inside quotes, the next byte is taken literally.
+    Escaped,
+    /// Right after a `\r` that ended a cell, a `\n` is part of the same line terminator.
+    AfterCR,
+}
+
+pub(crate) struct Splitter {
+    dialect: Dialect,
+    state: State,
+    cell_start: usize,
+    pos: usize,  // next byte to scan
+    offset: u64, // offset of the buffer in the input
+    row_start: Position,
+    row_open: bool, // a cell of the current row was already yielded
+    lines: u64,
+    prev_cr: bool,
+    closed: bool,       // the last byte closed a quoted cell, so a quote reopens it
+    opened_at: usize,   // quote that opened the quoted cell being split
+    lines_at_open: u64, // lines before that quote
+    trailing: bool,     // `finish` flushed a last row without a line terminator
+}
+
+impl Splitter {
+    pub(crate) fn new(dialect: Dialect) -> Self {
+        Splitter::starting_at(dialect, 0)
+    }
+
+    /// Splitter for an input whose first byte is at `byte` in the file. Positions are
+    /// counted from there, the first row being record 0 on line 1.
+    pub(crate) fn starting_at(dialect: Dialect, byte: u64) -> Self {
+        Splitter {
+            dialect,
+            state: State::Unquoted,
+            cell_start: 0,
+            pos: 0,
+            offset: byte,
+            row_start: Position {
+                record: 0,
+                line: 1,
+                byte,
+            },
+            row_open: false,
+            lines: 0,
+            prev_cr: false,
+            closed: false,
+            opened_at: 0,
+            lines_at_open: 0,
+            trailing: false,
+        }
+    }
+
+    /// Splitter resuming at the start of a row found earlier, e.g. through an `Index`, with
+    /// the row at `at` in the buffer. Positions carry on from `row`.
+    pub(crate) fn resuming(dialect: Dialect, row: Position, at: usize) -> Self {
+        let mut splitter = Splitter::starting_at(dialect, row.byte - at as u64);
+        splitter.row_start = row;
+        splitter.lines = row.line - 1;
+        splitter.cell_start = at;
+        splitter.pos = at;
+        splitter
+    }
+
+    /// Start of the bytes the splitter still needs, i.e. the cell being split.
+    pub(crate) fn consumed(&self) -> usize {
+        self.cell_start
+    }
+
+    /// Whether `finish` flushed a last row that had no line terminator.
+    pub(crate) fn trailing(&self) -> bool {
+        self.trailing
+    }
+
+    /// Position of the row being split, i.e. the next one after those already yielded.
+    pub(crate) fn row_start(&self) -> Position {
+        self.row_start
+    }
+
+    /// Tells the splitter the first `n` bytes of the buffer were dropped.
+    pub(crate) fn shift(&mut self, n: usize) {
+        self.cell_start -= n;
+        self.pos -= n;
+        self.opened_at = self.opened_at.saturating_sub(n);
+        self.offset += n as u64;
+    }
+
+    /// Scans the bytes appended to `buf` since the last call.
+    ///
+    /// Without an escape byte, quotes simply toggle the state, so whole blocks are handled
+    /// at once with bitmasks, see `split_block`. Bytes in the middle of an escape or of a
+    /// `\r\n` pair, the last bytes that don't fill a block and blocks with a stray quote
+    /// go through `step`.
+    pub(crate) fn split<F>(&mut self, buf: &[u8], mut on_boundary: F)
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        let blocks = self.dialect.escape.is_none();
+        let mut i = self.pos;
+
+        while i < buf.len() {
+            let toggling = matches!(self.state, State::Unquoted | State::Quoted);
+
+            if blocks && toggling && buf.len() - i >= BLOCK {
+                if !self.split_block(buf, i, &mut on_boundary) {
+                    for j in i..i + BLOCK {
+                        self.step(buf, j, &mut on_boundary);
+                    }
+                }
+                i += BLOCK;
+            } else {
+                self.step(buf, i, &mut on_boundary);
+                i += 1;
+            }
+        }
+
+        self.pos = buf.len();
+    }
+
+    fn step<F>(&mut self, buf: &[u8], i: usize, on_boundary: &mut F)
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        let byte = buf[i];
+        let closed = mem::take(&mut self.closed);
+        if self.state == State::AfterCR {
+            self.state = State::Unquoted;
+            if byte == LF {
+                self.prev_cr = false;
+                self.end_row(i + 1, on_boundary);
+                return;
+            }
+            self.end_row(i, on_boundary);
+        }
+
+        if byte == CR || (byte == LF && !self.prev_cr) {
+            self.lines += 1;
+        }
+        self.prev_cr = byte == CR;
+
+        match self.state {
+            State::Quoted if Some(byte) == self.dialect.escape => self.state = State::Escaped,
+            State::Quoted if byte == self.dialect.quote => {
+                self.state = State::Unquoted;
+                self.closed = true;
+            }
+            State::Quoted => {}
+            State::Escaped => self.state = State::Quoted,
+            State::Unquoted | State::AfterCR if byte == self.dialect.quote => {
+                if closed || self.at_cell_start(buf, i) {
+                    self.state = State::Quoted;
+                    self.opened_at = i;
+                    self.lines_at_open = self.lines;
+                } else {
+                    on_boundary(BoundaryEvent::Anomaly(Anomaly {
+                        kind: AnomalyKind::StrayQuote,
+                        position: self.row_start,
+                        line: self.lines + 1,
+                        byte: self.offset + i as u64,
+                    }));
+                }
+            }
+            State::Unquoted | State::AfterCR => self.unquoted(byte, i, on_boundary),
+        }
+    }
+
+    /// Whether the byte at `i` starts its cell, leading whitespace aside when the dialect
+    /// trims it, so a quote there opens a quoted cell.
+    fn at_cell_start(&self, buf: &[u8], i: usize) -> bool {
+        let blank = |b: &u8| *b == b' ' || *b == b'\t';
+        i == self.cell_start
+            || (self.dialect.trim == Trim::Whitespace && buf[self.cell_start..i].iter().all(blank))
+    }
+
+    /// Takes the quote left open at the end of the input literally, after `finish`
+    /// returned it, and reports it. The bytes after it are split again by the next
+    /// `finish`.
+    pub(crate) fn unquote<F>(&mut self, on_boundary: &mut F)
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        on_boundary(BoundaryEvent::Anomaly(Anomaly {
+            kind: AnomalyKind::UnterminatedQuote,
+            position: self.row_start,
+            line: self.lines_at_open + 1,
+            byte: self.offset + self.opened_at as u64,
+        }));
+
+        self.state = State::Unquoted;
+        self.pos = self.opened_at + 1;
+        self.lines = self.lines_at_open;
+        self.prev_cr = false;
+        self.closed = false;
+    }
+
+    /// Splits the block of `buf` starting at `start`, in the `Unquoted` or `Quoted` state.
+    ///
+    /// The quotes of the block give the bytes inside quotes through their prefix xor, and
+    /// the delimiters and terminators outside of them are the boundaries, visited in order.
+    ///
+    /// # Returns
+    /// - `false`, leaving the block to `step`, when a quote opening a quoted cell doesn't
+    ///   follow a boundary or a closing quote, i.e. may be a stray quote.
+    fn split_block<F>(&mut self, buf: &[u8], start: usize, on_boundary: &mut F) -> bool
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        let dialect = self.dialect;
+        let terminator = match dialect.terminator {
+            Terminator::CRLF => LF,
+            Terminator::Any(byte) => byte,
+        };
+        let [quotes, delimiters, crs, lfs, terminators] = simd::eq_masks(
+            &buf[start..],
+            [dialect.quote, dialect.delimiter, CR, LF, terminator],
+        );
+        let crlf = dialect.terminator == Terminator::CRLF;
+        let terminators = if crlf { crs | lfs } else { terminators };
+
+        let mut inside = simd::prefix_xor(quotes);
+        if self.state == State::Quoted {
+            inside = !inside;
+        }
+        let opening = quotes & inside;
+        let closing = quotes & !inside;
+        let cell_starts = (delimiters | terminators | closing) << 1
+            | (self.cell_start == start || self.closed) as u64;
+        if opening & !cell_starts != 0 {
+            return false;
+        }
+        // Like in `step`, a `\n` right after a `\r` isn't another line break.
+        let breaks = crs | (lfs & !(crs << 1 | self.prev_cr as u64));
+        let lines = self.lines;
+
+        let mut boundaries = (delimiters | terminators) & !inside;
+        self.state = match inside >> 63 {
+            1 => State::Quoted,
+            _ => State::Unquoted,
+        };
+        self.closed = closing >> 63 == 1;
+        if self.state == State::Quoted && opening != 0 {
+            let bit = 63 - opening.leading_zeros() as usize;
+            self.opened_at = start + bit;
+            self.lines_at_open = lines + (breaks & simd::below(bit)).count_ones() as u64;
+        }
+
+        while boundaries != 0 {
+            let bit = boundaries.trailing_zeros() as usize;
+            boundaries &= boundaries - 1;
+
+            let i = start + bit;
+            on_boundary(BoundaryEvent::NewCell(self.cell_start..i));
+            self.cell_start = i + 1;
+            self.row_open = true;
+
+            if terminators >> bit & 1 == 0 {
+                continue;
+            }
+
+            let mut end = i + 1;
+            if crlf && crs >> bit & 1 == 1 {
+                if bit == 63 {
+                    self.state = State::AfterCR;
+                    break;
+                }
+                if lfs >> (bit + 1) & 1 == 1 {
+                    boundaries &= !(1 << (bit + 1));
+                    end += 1;
+                }
+            }
+
+            self.lines = lines + (breaks & simd::below(end - start)).count_ones() as u64;
+            self.end_row(end, on_boundary);
+        }
+
+        self.lines = lines + breaks.count_ones() as u64;
+        self.prev_cr = crs >> 63 == 1;
+        true
+    }
+
+    /// Flushes the last row once the input is exhausted. A line terminator at the end of
+    /// the input doesn't produce an empty last row.
+    ///
+    /// # Returns
+    /// - The position of the last row when it ends inside quotes, in which case it isn't
+    ///   yielded. `unquote` can take the quote literally then, before finishing again.
+    pub(crate) fn finish<F>(&mut self, buf: &[u8], mut on_boundary: F) -> Option<Position>
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        self.split(buf, &mut on_boundary);
+
+        match self.state {
+            State::Quoted | State::Escaped => return Some(self.row_start),
+            State::AfterCR => self.end_row(buf.len(), &mut on_boundary),
+            State::Unquoted => {
+                if self.row_open || self.cell_start < buf.len() {
+                    self.trailing = true;
+                    on_boundary(BoundaryEvent::NewCell(self.cell_start..buf.len()));
+                    self.end_row(buf.len(), &mut on_boundary);
+                }
+            }
+        }
+
+        self.state = State::Unquoted;
+        self.cell_start = buf.len();
+        None
+    }
+
+    fn unquoted<F>(&mut self, byte: u8, i: usize, on_boundary: &mut F)
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        let dialect = &self.dialect;
+
+        if byte == dialect.delimiter {
+            on_boundary(BoundaryEvent::NewCell(self.cell_start..i));
+            self.cell_start = i + 1;
+            self.row_open = true;
+        } else if dialect.is_terminator(byte) {
+            on_boundary(BoundaryEvent::NewCell(self.cell_start..i));
+            self.cell_start = i + 1;
+            self.row_open = true;
+
+            if dialect.terminator == Terminator::CRLF && byte == CR {
+                self.state = State::AfterCR;
+            } else {
+                self.end_row(i + 1, on_boundary);
+            }
+        }
+    }
+
+    fn end_row<F>(&mut self, end: usize, on_boundary: &mut F)
+    where
+        F: FnMut(BoundaryEvent),
+    {
+        on_boundary(BoundaryEvent::NewLine(self.row_start));
+
+        self.row_start = Position {
+            record: self.row_start.record + 1,
+            line: self.lines + 1,
+            byte: self.offset + end as u64,
+        };
+        self.cell_start = end;
+        self.row_open = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Trim;
+    use proptest::prelude::*;
+
+    fn split(chunks: &[&[u8]], dialect: Dialect) -> Vec<Vec<Vec<u8>>> {
+        let mut splitter = Splitter::new(dialect);
+        let mut buf = Vec::new();
+        let mut lines = vec![vec![]];
+
+        let mut on_boundary = |buf: &[u8], boundary| match boundary {
+            BoundaryEvent::NewCell(c) => lines.last_mut().unwrap().push(buf[c].to_vec()),
+            BoundaryEvent::NewLine(_) => lines.push(vec![]),
+            BoundaryEvent::Anomaly(anomaly) => panic!("{anomaly}"),
+        };
+
+        for chunk in chunks {
+            buf.extend_from_slice(chunk);
+            splitter.split(&buf, |b| on_boundary(&buf, b));
+        }
+        assert_eq!(splitter.finish(&buf, |b| on_boundary(&buf, b)), None);
+
+        lines.pop();
+        lines
+    }
+
+    #[test]
+    fn split_default_dialect() {
+        let lines = split(&[b"a,\"b,\r\nc\"\r\nd,e"], Dialect::default());
+
+        assert_eq!(
+            lines,
+            vec![
+                vec![b"a".to_vec(), b"\"b,\r\nc\"".to_vec()],
+                vec![b"d".to_vec(), b"e".to_vec()],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_semicolon_single_quote() {
+        let dialect = Dialect {
+            delimiter: b';',
+            quote: b'\'',
+            escape: Some(b'\\'),
+            terminator: Terminator::CRLF,
+            trim: Trim::None,
+        };
+        let lines = split(&[b"'a;\\", b"'b';c\r", b"\nd;e\r\n"], dialect);
+
+        assert_eq!(
+            lines,
+            vec![
+                vec![b"'a;\\'b'".to_vec(), b"c".to_vec()],
+                vec![b"d".to_vec(), b"e".to_vec()],
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_split_between_chunks() {
+        let lines = split(&[b"a,b\r", b"\nc\r", b"\r", b"\n"], Dialect::default());
+
+        assert_eq!(
+            lines,
+            vec![
+                vec![b"a".to_vec(), b"b".to_vec()],
+                vec![b"c".to_vec()],
+                vec![b"".to_vec()],
+            ]
+        );
+    }
+
+    #[test]
+    fn shift_keeps_state() {
+        let mut splitter = Splitter::new(Dialect::default());
+        let mut buf = b"a,\"b,".to_vec();
+        let mut cells = Vec::new();
+
+        splitter.split(&buf, |_| {});
+        assert_eq!(splitter.consumed(), 2);
+
+        buf.drain(..2);
+        splitter.shift(2);
+        buf.extend_from_slice(b"c\"\n");
+        splitter.split(&buf, |b| {
+            if let BoundaryEvent::NewCell(c) = b {
+                cells.push(buf[c].to_vec());
+            }
+        });
+
+        assert_eq!(cells, vec![b"\"b,c\"".to_vec()]);
+        assert_eq!(splitter.row_start.byte, 8);
+    }
+
+    /// Splits `content` taking the quotes left open literally, like the reader does unless
+    /// strict, and returns the rows and the anomalies.
+    fn recover(content: &[u8], dialect: Dialect) -> (Vec<Vec<&[u8]>>, Vec<String>) {
+        let mut splitter = Splitter::new(dialect);
+        let (mut lines, mut anomalies) = (vec![vec![]], Vec::new());
+        let mut on_boundary = |boundary| match boundary {
+            BoundaryEvent::NewCell(c) => lines.last_mut().unwrap().push(&content[c]),
+            BoundaryEvent::NewLine(_) => lines.push(vec![]),
+            BoundaryEvent::Anomaly(anomaly) => anomalies.push(anomaly.to_string()),
+        };
+
+        splitter.split(content, &mut on_boundary);
+        while splitter.finish(content, &mut on_boundary).is_some() {
+            splitter.unquote(&mut on_boundary);
+        }
+
+        lines.pop();
+        (lines, anomalies)
+    }
+
+    #[test]
+    fn stray_quotes_are_literal() {
+        let (lines, anomalies) = recover(b"a,5\" b,\"c\"\"\"\nd,e\"\n", Dialect::default());
+
+        assert_eq!(
+            lines,
+            vec![vec![&b"a"[..], b"5\" b", b"\"c\"\"\""], vec![b"d", b"e\""],]
+        );
+        assert_eq!(
+            anomalies,
+            vec![
+                "Stray quote on line 1, byte 3, in record 0",
+                "Stray quote on line 2, byte 16, in record 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_literal() {
+        let mut splitter = Splitter::new(Dialect::default());
+        let content = b"a\n\"b,c\nd\n";
+        assert_eq!(splitter.finish(content, |_| {}).map(|p| p.byte), Some(2));
+
+        let (lines, anomalies) = recover(content, Dialect::default());
+
+        assert_eq!(lines, vec![vec![&b"a"[..]], vec![b"\"b", b"c"], vec![b"d"]]);
+        assert_eq!(
+            anomalies,
+            vec!["Unterminated quote on line 2, byte 2, in record 1"]
+        );
+    }
+
+    #[test]
+    fn quotes_after_trimmed_blanks() {
+        let content = b" \"a,b\" ,c\n";
+
+        let trimmed = Dialect {
+            trim: Trim::Whitespace,
+            ..Dialect::default()
+        };
+        let (lines, anomalies) = recover(content, trimmed);
+        assert_eq!(lines, vec![vec![&b" \"a,b\" "[..], b"c"]]);
+        assert!(anomalies.is_empty());
+
+        let (lines, anomalies) = recover(content, Dialect::default());
+        assert_eq!(lines, vec![vec![&b" \"a"[..], b"b\" ", b"c"]]);
+        assert_eq!(anomalies.len(), 2);
+    }
+
+    /// Cell ranges and row positions, as `Debug` strings.
+    fn events(
+        chunks: impl Iterator<Item = usize>,
+        content: &[u8],
+        dialect: Dialect,
+    ) -> Vec<String> {
+        let mut splitter = Splitter::new(dialect);
+        let mut events = Vec::new();
+        let mut on_boundary = |boundary| match boundary {
+            BoundaryEvent::NewCell(c) => events.push(format!("{c:?}")),
+            BoundaryEvent::NewLine(position) => events.push(format!("{position:?}")),
+            BoundaryEvent::Anomaly(anomaly) => events.push(format!("{anomaly:?}")),
+        };
+
+        let mut end = 0;
+        for len in chunks {
+            end = (end + len).min(content.len());
+            splitter.split(&content[..end], &mut on_boundary);
+        }
+        let unterminated = splitter.finish(content, &mut on_boundary);
+
+        events.push(format!("{unterminated:?} {:?}", splitter.row_start()));
+        events
+    }
+
+    proptest! {
+        #[test]
+        fn blocks_like_bytes(
+            content in prop::collection::vec(
+                prop::sample::select(b"ab ,;\"\r\n".to_vec()),
+                0..512,
+            ),
+            terminator in prop_oneof![Just(Terminator::CRLF), Just(Terminator::Any(b';'))],
+            trim in prop_oneof![Just(Trim::None), Just(Trim::Whitespace)],
+        ) {
+            let dialect = Dialect { terminator, trim, ..Dialect::default() };
+
+            let blocks = events([content.len()].into_iter(), &content, dialect);
+            let bytes = events((0..content.len()).map(|_| 1), &content, dialect);
+
+            prop_assert_eq!(blocks, bytes);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{Read, Write};
+use std::ops::Range;
+
+use crate::headers::column_of;
+use crate::{CellParser, CsvError, CsvReader, CsvWriter, Expr};
+
+/// Writes the given columns of the remaining rows of `reader` to `out`, in the given
+/// order, after a header row with their names when the reader has headers.
+///
+/// Columns are named after the header, or given by 0-based index. Cells are written as
+/// they are in the file, so `out` should use the dialect of the reader. Missing cells are
+/// written empty.
+///
+/// # Returns
+/// - The number of rows written, the header row excluded.
+///
+/// # Example
+/// ```
+/// use process_csv::{CsvReader, CsvWriter, Dialect, select};
+///
+/// let mut reader = CsvReader::from_reader(&b"Name,Age,Country\nBob,30,UK\n"[..], Dialect::default());
+/// let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+///
+/// select(&mut reader, &["Country", "0"], &mut out).unwrap();
+/// assert_eq!(out.into_inner().unwrap(), b"Country,Name\r\nUK,Bob\r\n");
+/// ```
+pub fn select<R: Read, W: Write>(
+    reader: &mut CsvReader<R>,
+    columns: &[&str],
+    out: &mut CsvWriter<W>,
+) -> Result<u64, CsvError> {
+    let headers = reader.headers()?;
+    let columns = columns
+        .iter()
+        .map(|name| column_of(headers, name))
+        .collect::<Result<Vec<usize>, CsvError>>()?;
+    if let Some(headers) = headers {
+        out.write_record(columns.iter().map(|&i| headers.get(i).unwrap_or_default()))?;
+    }
+
+    let mut rows = 0;
+    while let Some(row) = reader.next_row() {
+        let row = row?;
+        out.write_raw_record(columns.iter().map(|&i| row.get(i).unwrap_or_default()))?;
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// Writes the remaining rows of `reader` matching `expr` to `out`, after the header row.
+///
+/// # Returns
+/// - The number of rows written, the header row excluded.
+pub fn filter<R: Read, W: Write>(
+    reader: &mut CsvReader<R>,
+    expr: &Expr,
+    out: &mut CsvWriter<W>,
+) -> Result<u64, CsvError> {
+    let parser = CellParser::from(reader.dialect());
+    let headers = reader.headers()?;
+    let filter = expr.bind(headers)?.with_parser(parser);
+    if let Some(headers) = headers {
+        out.write_record(headers.iter())?;
+    }
+
+    let mut rows = 0;
+    while let Some(row) = reader.next_row() {
+        let row = row?;
+        if filter.matches(&row)? {
+            out.write_raw_record(row.iter())?;
+            rows += 1;
+        }
+    }
+    Ok(rows)
+}
+
+/// Writes the rows of `reader` in `range` to `out`, after the header row. Rows are counted
+/// from 0 and from the next one to be read, the header row excluded, and reading stops at
+/// the end of the range.
+///
+/// # Returns
+/// - The number of rows written, the header row excluded.
+pub fn slice<R: Read, W: Write>(
+    reader: &mut CsvReader<R>,
+    range: Range<u64>,
+    out: &mut CsvWriter<W>,
+) -> Result<u64, CsvError> {
+    if let Some(headers) = reader.headers()? {
+        out.write_record(headers.iter())?;
+    }
+
+    let mut rows = 0;
+    let mut i = 0;
+    while i < range.end
+        && let Some(row) = reader.next_row()
+    {
+        let row = row?;
+        if range.contains(&i) {
+            out.write_raw_record(row.iter())?;
+            rows += 1;
+        }
+        i += 1;
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    const USERS: &[u8] = b"Name,Age,Country\n\
+        Alice,25,USA\n\
+        \"Smith, Bob\",31,UK\n\
+        Carol,40,\"USA\"\n";
+
+    fn run<F>(has_headers: bool, transform: F) -> String
+    where
+        F: FnOnce(&mut CsvReader<&[u8]>, &mut CsvWriter<Vec<u8>>) -> Result<u64, CsvError>,
+    {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        if !has_headers {
+            reader = reader.without_headers();
+        }
+        let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+
+        transform(&mut reader, &mut out).unwrap();
+        String::from_utf8(out.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn select_columns() {
+        assert_eq!(
+            run(true, |r, out| select(r, &["Country", "Name", "Name"], out)),
+            "Country,Name,Name\r\nUSA,Alice,Alice\r\nUK,\"Smith, Bob\",\"Smith, Bob\"\r\n\"USA\",Carol,Carol\r\n"
+        );
+        assert_eq!(
+            run(false, |r, out| select(r, &["1"], out)),
+            "Age\r\n25\r\n31\r\n40\r\n"
+        );
+
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let mut out = CsvWriter::from_writer(Vec::new(), Dialect::default());
+        let e = select(&mut reader, &["City"], &mut out).unwrap_err();
+        assert!(matches!(e, CsvError::UnknownColumn(_)));
+    }
+
+    #[test]
+    fn filter_rows() {
+        let expr = Expr::parse("Country == 'USA' && Age > 30").unwrap();
+        assert_eq!(
+            run(true, |r, out| filter(r, &expr, out)),
+            "Name,Age,Country\r\nCarol,40,\"USA\"\r\n"
+        );
+
+        let expr = Expr::parse("`0` == 'Smith, Bob'").unwrap();
+        assert_eq!(
+            run(false, |r, out| filter(r, &expr, out)),
+            "\"Smith, Bob\",31,UK\r\n"
+        );
+    }
+
+    #[test]
+    fn slices() {
+        assert_eq!(
+            run(true, |r, out| slice(r, 1..2, out)),
+            "Name,Age,Country\r\n\"Smith, Bob\",31,UK\r\n"
+        );
+        assert_eq!(
+            run(false, |r, out| slice(r, 0..2, out)),
+            "Name,Age,Country\r\nAlice,25,USA\r\n"
+        );
+        assert_eq!(
+            run(true, |r, out| slice(r, 5..9, out)),
+            "Name,Age,Country\r\n"
+        );
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::headers::column_of;
+use crate::{CellParser, CsvError, CsvReader, CsvWriter, ParseError, Position};
+
+/// A check on the values of a column.
+///
+/// Empty cells only fail `Required`, the other rules skip them.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    Required,
+    /// The whole value matches the pattern, see `Rule::pattern`.
+    Pattern(Regex),
+    /// The value is a number between the bounds, both included.
+    Range {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    /// The value is one of the listed ones.
+    OneOf(Vec<String>),
+    Email,
+    /// No two rows have the same value in the column.
+    Unique,
+}
+
+impl Rule {
+    /// Compiles a `Rule::Pattern` matching the whole value, e.g. `[A-Z]{2}` for `FR`
+    /// but not `FRA`.
+    pub fn pattern(pattern: &str) -> Result<Rule, regex::Error> {
+        Regex::new(&format!("^(?:{pattern})$")).map(Rule::Pattern)
+    }
+
+    /// Checks a value of the column, `Unique` aside as it needs the previous rows.
+    ///
+    /// # Returns
+    /// - `Err(message)` describing why the value doesn't pass.
+    fn check(&self, value: &str, parser: &CellParser) -> Result<(), String> {
+        if value.is_empty() {
+            return match self {
+                Rule::Required => Err("is required".to_string()),
+                _ => Ok(()),
+            };
+        }
+
+        match self {
+            Rule::Pattern(regex) => {
+                let pattern = regex.as_str();
+                let pattern = pattern
+                    .strip_prefix("^(?:")
+                    .and_then(|p| p.strip_suffix(")$"))
+                    .unwrap_or(pattern);
+                regex
+                    .is_match(value)
+                    .then_some(())
+                    .ok_or_else(|| format!("doesn't match {pattern:?}"))
+            }
+            Rule::Range { min, max } => {
+                let in_range = parser.to_float(value.as_bytes().to_vec()).is_ok_and(|n| {
+                    min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max)
+                });
+                let bounds = match (min, max) {
+                    (Some(min), Some(max)) => format!(" between {min} and {max}"),
+                    (Some(min), None) => format!(" of at least {min}"),
+                    (None, Some(max)) => format!(" of at most {max}"),
+                    (None, None) => String::new(),
+                };
+                in_range
+                    .then_some(())
+                    .ok_or_else(|| format!("isn't a number{bounds}"))
+            }
+            Rule::OneOf(values) => values
+                .iter()
+                .any(|v| v == value)
+                .then_some(())
+                .ok_or_else(|| format!("isn't one of {values:?}")),
+            Rule::Email => is_email(value)
+                .then_some(())
+                .ok_or_else(|| "isn't an email address".to_string()),
+            Rule::Required | Rule::Unique => Ok(()),
+        }
+    }
+}
+
+/// Checks the rows of a file against rules declared per column, reporting every value
+/// that doesn't pass instead of stopping at the first one.
+///
+/// Columns are named after the header, or given by 0-based index for files without one.
+///
+/// # Example
+/// ```
+/// use process_csv::{CsvReader, Dialect, Rule, Validator};
+///
+/// let content = b"Name,Age\nBob,30\n,abc\n";
+/// let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+///
+/// let report = Validator::new()
+///     .rule("Name", Rule::Required)
+///     .rule("Age", Rule::Range { min: Some(0.0), max: Some(150.0) })
+///     .validate(&mut reader)
+///     .unwrap();
+///
+/// assert_eq!(report.rejected, 1);
+/// assert_eq!(report.violations.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    columns: Vec<(String, Vec<Rule>)>,
+}
+
+/// Outcome of `Validator::validate`.
+#[derive(Debug)]
+pub struct Report {
+    /// Number of rows checked, the header row excluded.
+    pub rows: u64,
+    /// Number of rows with at least one violation.
+    pub rejected: u64,
+    /// Values that don't pass a rule, in the order of the file.
+    pub violations: Vec<ParseError>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for violation in &self.violations {
+            writeln!(f, "{violation}")?;
+        }
+        write!(f, "{} of {} rows rejected", self.rejected, self.rows)
+    }
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator::default()
+    }
+
+    /// Adds a rule on the column called `name`.
+    pub fn rule(mut self, name: &str, rule: Rule) -> Self {
+        match self.columns.iter_mut().find(|(column, _)| column == name) {
+            Some((_, rules)) => rules.push(rule),
+            None => self.columns.push((name.to_string(), vec![rule])),
+        }
+        self
+    }
+
+    /// Loads the rules from a TOML document with a `[[column]]` table per column:
+    ///
+    /// ```toml
+    /// [[column]]
+    /// name = "Age"
+    /// required = true
+    /// min = 0
+    /// max = 150
+    ///
+    /// [[column]]
+    /// name = "Email"
+    /// email = true
+    /// unique = true
+    ///
+    /// [[column]]
+    /// name = "Country"
+    /// one_of = ["France", "Germany"]
+    /// pattern = "[A-Z][a-z]+"
+    /// ```
+    pub fn from_toml(text: &str) -> Result<Self, CsvError> {
+        let spec: Spec = toml::from_str(text).map_err(|e| CsvError::Rules(e.to_string()))?;
+
+        let mut validator = Validator::new();
+        for column in spec.columns {
+            let name = &column.name;
+            if column.required {
+                validator = validator.rule(name, Rule::Required);
+            }
+            if let Some(pattern) = &column.pattern {
+                let rule = Rule::pattern(pattern).map_err(|e| CsvError::Rules(e.to_string()))?;
+                validator = validator.rule(name, rule);
+            }
+            if column.min.is_some() || column.max.is_some() {
+                let (min, max) = (column.min, column.max);
+                validator = validator.rule(name, Rule::Range { min, max });
+            }
+            if let Some(values) = column.one_of {
+                validator = validator.rule(name, Rule::OneOf(values));
+            }
+            if column.email {
+                validator = validator.rule(name, Rule::Email);
+            }
+            if column.unique {
+                validator = validator.rule(name, Rule::Unique);
+            }
+        }
+
+        Ok(validator)
+    }
+
+    /// Checks the remaining rows of `reader`.
+    ///
+    /// Rows with another number of cells than the header, and cells that aren't valid
+    /// UTF-8, are violations like the values failing a rule.
+    ///
+    /// # Returns
+    /// - `Err(CsvError::UnknownColumn)` when a rule names a column missing from the header.
+    /// - `Err(CsvError)` for the first row that can't be read at all, e.g. an I/O error.
+    pub fn validate<R: Read>(&self, reader: &mut CsvReader<R>) -> Result<Report, CsvError> {
+        self.run(reader, None::<&mut CsvWriter<io::Sink>>)
+    }
+
+    /// Like `validate`, also writing the header and the rejected rows to `rejects`, as
+    /// they are in the file.
+    pub fn validate_with_rejects<R: Read, W: Write>(
+        &self,
+        reader: &mut CsvReader<R>,
+        rejects: W,
+    ) -> Result<Report, CsvError> {
+        let mut rejects = CsvWriter::from_writer(rejects, reader.dialect());
+        let report = self.run(reader, Some(&mut rejects))?;
+        rejects.flush()?;
+        Ok(report)
+    }
+
+    fn run<R: Read, W: Write>(
+        &self,
+        reader: &mut CsvReader<R>,
+        mut rejects: Option<&mut CsvWriter<W>>,
+    ) -> Result<Report, CsvError> {
+        let parser = CellParser::from(reader.dialect());
+        let headers = reader.headers()?.cloned();
+
+        let mut columns = Vec::with_capacity(self.columns.len());
+        for (name, rules) in &self.columns {
+            let column = column_of(headers.as_ref(), name)?;
+            columns.push((column, name.as_str(), rules));
+        }
+        if let (Some(rejects), Some(headers)) = (rejects.as_mut(), &headers) {
+            rejects.write_record(headers.iter())?;
+        }
+
+        // Values seen in the `Unique` columns, with the first row they are in.
+        let mut seen: Vec<HashMap<String, Position>> = vec![HashMap::new(); columns.len()];
+        let mut report = Report {
+            rows: 0,
+            rejected: 0,
+            violations: Vec::new(),
+        };
+
+        while let Some(row) = reader.next_unchecked_row() {
+            let row = row?;
+            let position = row.position();
+            let violations = report.violations.len();
+            report.rows += 1;
+
+            // The cells of a short or long row can't be told apart, its rules aren't checked.
+            let expected = headers.as_ref().map_or(row.len(), |h| h.len());
+            if row.len() != expected {
+                let message = format!(
+                    "expected {expected} cells as in the header, found {}",
+                    row.len()
+                );
+                let violation = ParseError::new(message, None);
+                report.violations.push(violation.at_row(position));
+            }
+
+            let checked_columns = columns.iter().zip(&mut seen);
+            for ((column, name, rules), seen) in checked_columns.filter(|_| row.len() == expected) {
+                let raw = row.get(*column).unwrap_or_default();
+                let Ok(value) = parser.to_string(raw.to_vec()) else {
+                    let value = String::from_utf8_lossy(raw);
+                    let violation = ParseError::new("isn't valid UTF-8", Some(&value));
+                    report
+                        .violations
+                        .push(violation.located(position, *column, Some(name)));
+                    continue;
+                };
+
+                for rule in rules.iter() {
+                    let checked = match rule {
+                        Rule::Unique if !value.is_empty() => match seen.get(&value) {
+                            Some(first) => Err(format!("is already in record {}", first.record)),
+                            None => {
+                                seen.insert(value.clone(), position);
+                                Ok(())
+                            }
+                        },
+                        rule => rule.check(&value, &parser),
+                    };
+                    if let Err(message) = checked {
+                        let violation = ParseError::new(message, Some(&value));
+                        report
+                            .violations
+                            .push(violation.located(position, *column, Some(name)));
+                    }
+                }
+            }
+
+            if report.violations.len() > violations {
+                report.rejected += 1;
+                if let Some(rejects) = rejects.as_mut() {
+                    rejects.write_raw_record(row.iter())?;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default, rename = "column")]
+    columns: Vec<ColumnSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnSpec {
+    name: String,
+    #[serde(default)]
+    required: bool,
+    pattern: Option<String>,
+    min: Option<f64>,
+    max: Option<f64>,
+    one_of: Option<Vec<String>>,
+    #[serde(default)]
+    email: bool,
+    #[serde(default)]
+    unique: bool,
+}
+
+/// A local part and a dotted domain around a single `@`, without whitespace.
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    let labels_ok = domain.split('.').count() > 1 && domain.split('.').all(|l| !l.is_empty());
+
+    !local.is_empty() && !domain.contains('@') && labels_ok && !value.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Dialect;
+
+    const USERS: &[u8] = b"Name,Age,Email,Country\n\
+        Alice,30,alice@example.com,France\n\
+        ,abc,bob@example,Spain\n\
+        \"Carol, Jr.\",151,alice@example.com,France\n\
+        Dave,,dave@example.org,\n";
+
+    fn describe(report: &Report) -> Vec<String> {
+        report.violations.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn rules_in_code() {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let validator = Validator::new()
+            .rule("Name", Rule::Required)
+            .rule(
+                "Age",
+                Rule::Range {
+                    min: Some(0.0),
+                    max: Some(150.0),
+                },
+            )
+            .rule("Email", Rule::Email)
+            .rule("Email", Rule::Unique)
+            .rule(
+                "Country",
+                Rule::OneOf(vec!["France".into(), "Germany".into()]),
+            )
+            .rule("Country", Rule::pattern("[A-Z][a-z]+").unwrap());
+
+        let report = validator.validate(&mut reader).unwrap();
+
+        assert_eq!(
+            describe(&report),
+            vec![
+                "at record 2, line 3, byte 57, column 0 (\"Name\"), value \"\": is required",
+                "at record 2, line 3, byte 57, column 1 (\"Age\"), value \"abc\": \
+                 isn't a number between 0 and 150",
+                "at record 2, line 3, byte 57, column 2 (\"Email\"), value \"bob@example\": \
+                 isn't an email address",
+                "at record 2, line 3, byte 57, column 3 (\"Country\"), value \"Spain\": \
+                 isn't one of [\"France\", \"Germany\"]",
+                "at record 3, line 4, byte 80, column 1 (\"Age\"), value \"151\": \
+                 isn't a number between 0 and 150",
+                "at record 3, line 4, byte 80, column 2 (\"Email\"), value \"alice@example.com\": \
+                 is already in record 1",
+            ]
+        );
+        assert_eq!((report.rows, report.rejected), (4, 2));
+        assert!(report.to_string().ends_with("2 of 4 rows rejected"));
+    }
+
+    #[test]
+    fn rules_from_toml() {
+        let validator = Validator::from_toml(
+            r#"
+            [[column]]
+            name = "Age"
+            required = true
+            min = 18
+
+            [[column]]
+            name = "Country"
+            pattern = "[A-Z][a-z]+"
+            "#,
+        )
+        .unwrap();
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+
+        let report = validator.validate(&mut reader).unwrap();
+
+        let rejected: Vec<(u64, Option<usize>)> = report
+            .violations
+            .iter()
+            .map(|v| (v.position().unwrap().record, v.column()))
+            .collect();
+        assert_eq!(rejected, vec![(2, Some(1)), (4, Some(1))]);
+
+        let e = Validator::from_toml("[[column]]\nname = \"Age\"\nmaximum = 3\n").unwrap_err();
+        assert!(e.to_string().contains("unknown field `maximum`"));
+        let e = Validator::from_toml("[[column]]\nname = \"Age\"\npattern = \"(\"\n").unwrap_err();
+        assert!(matches!(e, CsvError::Rules(_)));
+    }
+
+    #[test]
+    fn rejects_file() {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let validator = Validator::new().rule(
+            "Age",
+            Rule::Range {
+                min: None,
+                max: Some(150.0),
+            },
+        );
+
+        let mut rejects = Vec::new();
+        let report = validator
+            .validate_with_rejects(&mut reader, &mut rejects)
+            .unwrap();
+
+        assert_eq!(report.rejected, 2);
+        assert_eq!(
+            String::from_utf8(rejects).unwrap(),
+            "Name,Age,Email,Country\r\n\
+             ,abc,bob@example,Spain\r\n\
+             \"Carol, Jr.\",151,alice@example.com,France\r\n"
+        );
+    }
+
+    #[test]
+    fn unknown_columns() {
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default());
+        let e = Validator::new()
+            .rule("Mail", Rule::Email)
+            .validate(&mut reader)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "No column named \"Mail\"");
+
+        let mut reader = CsvReader::from_reader(USERS, Dialect::default()).without_headers();
+        let report = Validator::new()
+            .rule(
+                "1",
+                Rule::Range {
+                    min: Some(0.0),
+                    max: None,
+                },
+            )
+            .validate(&mut reader)
+            .unwrap();
+        assert_eq!(report.rejected, 2);
+    }
+
+    #[test]
+    fn unreadable_rows() {
+        let content = b"Name,Age\nAl\xff,30\nBob\nCarol,40\n";
+        let mut reader = CsvReader::from_reader(&content[..], Dialect::default());
+        let validator = Validator::new().rule("Name", Rule::Required);
+
+        let mut rejects = Vec::new();
+        let report = validator
+            .validate_with_rejects(&mut reader, &mut rejects)
+            .unwrap();
+
+        assert_eq!(
+            describe(&report),
+            vec![
+                "at record 1, line 2, byte 9, column 0 (\"Name\"), value \"Al\u{fffd}\": \
+                 isn't valid UTF-8",
+                "at record 2, line 3, byte 16: expected 2 cells as in the header, found 1",
+            ]
+        );
+        assert_eq!((report.rows, report.rejected), (3, 2));
+        assert_eq!(rejects, b"Name,Age\r\nAl\xff,30\r\nBob\r\n");
+    }
+
+    #[test]
+    fn emails() {
+        assert!(is_email("bob@example.com"));
+        assert!(is_email("bob.smith+csv@mail.example.org"));
+        assert!(!is_email("bob@example"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("bob@example..com"));
+        assert!(!is_email("bob@@example.com"));
+        assert!(!is_email("bob smith@example.com"));
+    }
+}
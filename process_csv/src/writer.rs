@@ -0,0 +1,250 @@
+/// `CsvWriter` is the counterpart of `CsvReader`: it takes rows and writes them following
+/// a `Dialect`, quoting the cells that would otherwise be split or altered when read back.
+///
+/// Cells are quoted when they contain the delimiter, the quote or escape bytes, or a line
+/// terminator. With `Trim::Whitespace`, cells starting or ending with whitespace are quoted
+/// too. Quotes inside a quoted cell are doubled, or prefixed with the escape byte when the
+/// dialect has one, the inverse of what `CellParser` does when reading.
+///
+/// Writes go through a buffer, call `flush` or `into_inner` to make sure everything reached
+/// the underlying writer.
+use std::io::{BufWriter, Write};
+
+use serde::Serialize;
+
+use crate::ser::RowSerializer;
+use crate::{CR, CsvError, Dialect, LF, Terminator, Trim};
+
+pub struct CsvWriter<W: Write> {
+    out: BufWriter<W>,
+    dialect: Dialect,
+    has_headers: bool,
+    headers_written: bool,
+    cells: Vec<Vec<u8>>,
+    names: Vec<String>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn from_writer(writer: W, dialect: Dialect) -> Self {
+        CsvWriter {
+            out: BufWriter::with_capacity(1024 * 8, writer), // 8KB
+            dialect,
+            has_headers: true,
+            headers_written: false,
+            cells: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+
+    /// Stops `serialize` from writing a header row before the first struct or map.
+    pub fn without_headers(mut self) -> Self {
+        self.has_headers = false;
+        self
+    }
+
+    /// Writes a row made of byte cells or strings.
+    ///
+    /// # Example
+    /// ```
+    /// use process_csv::{CsvWriter, Dialect};
+    ///
+    /// let mut writer = CsvWriter::from_writer(Vec::new(), Dialect::default());
+    /// writer.write_record(["Name", "Motto"]).unwrap();
+    /// writer.write_record(["Alice", "Say \"hi\", then leave"]).unwrap();
+    ///
+    /// let out = writer.into_inner().unwrap();
+    /// assert_eq!(out, b"Name,Motto\r\nAlice,\"Say \"\"hi\"\", then leave\"\r\n");
+    /// ```
+    pub fn write_record<I, C>(&mut self, cells: I) -> Result<(), CsvError>
+    where
+        I: IntoIterator<Item = C>,
+        C: AsRef<[u8]>,
+    {
+        for (i, cell) in cells.into_iter().enumerate() {
+            if i > 0 {
+                self.out.write_all(&[self.dialect.delimiter])?;
+            }
+            self.write_cell(cell.as_ref())?;
+        }
+
+        self.end_row()
+    }
+
+    /// Writes the cells as they are, for raw cells split by a `CsvReader` with the same
+    /// dialect, which still have their quotes.
+    pub(crate) fn write_raw_record<'c>(
+        &mut self,
+        cells: impl IntoIterator<Item = &'c [u8]>,
+    ) -> Result<(), CsvError> {
+        for (i, cell) in cells.into_iter().enumerate() {
+            if i > 0 {
+                self.out.write_all(&[self.dialect.delimiter])?;
+            }
+            self.out.write_all(cell)?;
+        }
+
+        self.end_row()
+    }
+
+    /// Writes a `Serialize` value as a row. Structs and maps give one cell per field, and
+    /// unless `without_headers` was called, their field names are written as a header row
+    /// before the first one.
+    pub fn serialize<T: Serialize>(&mut self, value: &T) -> Result<(), CsvError> {
+        let mut cells = std::mem::take(&mut self.cells);
+        let mut names = std::mem::take(&mut self.names);
+        cells.clear();
+        names.clear();
+
+        value.serialize(&mut RowSerializer {
+            cells: &mut cells,
+            names: &mut names,
+        })?;
+
+        if self.has_headers && !self.headers_written && !names.is_empty() {
+            self.write_record(&names)?;
+        }
+        self.headers_written = true;
+        self.write_record(&cells)?;
+
+        self.cells = cells;
+        self.names = names;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), CsvError> {
+        self.out.flush().map_err(|e| e.into())
+    }
+
+    /// Flushes the buffer and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W, CsvError> {
+        self.out.into_inner().map_err(|e| e.into_error().into())
+    }
+
+    fn end_row(&mut self) -> Result<(), CsvError> {
+        match self.dialect.terminator {
+            Terminator::CRLF => self.out.write_all(&[CR, LF])?,
+            Terminator::Any(b) => self.out.write_all(&[b])?,
+        }
+        Ok(())
+    }
+
+    fn write_cell(&mut self, cell: &[u8]) -> Result<(), CsvError> {
+        if !self.needs_quotes(cell) {
+            return self.out.write_all(cell).map_err(|e| e.into());
+        }
+
+        let Dialect { quote, escape, .. } = self.dialect;
+
+        self.out.write_all(&[quote])?;
+
+        let mut start = 0;
+        for (i, &byte) in cell.iter().enumerate() {
+            if byte == quote || Some(byte) == escape {
+                self.out.write_all(&cell[start..i])?;
+                self.out.write_all(&[escape.unwrap_or(quote)])?;
+                start = i;
+            }
+        }
+        self.out.write_all(&cell[start..])?;
+
+        self.out.write_all(&[quote])?;
+        Ok(())
+    }
+
+    fn needs_quotes(&self, cell: &[u8]) -> bool {
+        let d = &self.dialect;
+
+        let trimmed = d.trim == Trim::Whitespace
+            && (cell.first().is_some_and(u8::is_ascii_whitespace)
+                || cell.last().is_some_and(u8::is_ascii_whitespace));
+
+        trimmed
+            || cell.iter().any(|&b| {
+                b == d.delimiter
+                    || b == d.quote
+                    || Some(b) == d.escape
+                    || b == CR
+                    || b == LF
+                    || d.is_terminator(b)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CellParser, CsvReader};
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct User {
+        name: String,
+        age: u8,
+        mail: Option<String>,
+    }
+
+    fn written(writer: CsvWriter<Vec<u8>>) -> String {
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn quote_when_needed() {
+        let mut writer = CsvWriter::from_writer(Vec::new(), Dialect::default());
+        writer
+            .write_record([&b"plain"[..], b"a,b", b"line\nbreak", b"\"q\"", b""])
+            .unwrap();
+
+        assert_eq!(
+            written(writer),
+            "plain,\"a,b\",\"line\nbreak\",\"\"\"q\"\"\",\r\n"
+        );
+    }
+
+    #[test]
+    fn escape_with_dialect() {
+        let dialect = Dialect {
+            delimiter: b';',
+            quote: b'\'',
+            escape: Some(b'\\'),
+            terminator: Terminator::Any(LF),
+            trim: Trim::Whitespace,
+        };
+        let mut writer = CsvWriter::from_writer(Vec::new(), dialect);
+        writer
+            .write_record(["it's", "a;b", " padded", "c\\d"])
+            .unwrap();
+
+        assert_eq!(written(writer), "'it\\'s';'a;b';' padded';'c\\\\d'\n");
+
+        let parser = CellParser::from(dialect);
+        assert_eq!(parser.to_string(b"'it\\'s'".to_vec()).unwrap(), "it's");
+        assert_eq!(parser.to_string(b"'c\\\\d'".to_vec()).unwrap(), "c\\d");
+    }
+
+    #[test]
+    fn round_trip_structs() {
+        let users = vec![
+            User {
+                name: "Johnson, \"Al\"".to_string(),
+                age: 25,
+                mail: Some("al@example.com".to_string()),
+            },
+            User {
+                name: "Bob".to_string(),
+                age: 30,
+                mail: None,
+            },
+        ];
+
+        let mut writer = CsvWriter::from_writer(Vec::new(), Dialect::default());
+        for user in &users {
+            writer.serialize(user).unwrap();
+        }
+
+        let written = writer.into_inner().unwrap();
+        let mut reader = CsvReader::from_reader(&written[..], Dialect::default()).with_watermark(4);
+        let read: Vec<User> = reader.deserialize().map(Result::unwrap).collect();
+
+        assert_eq!(read, users);
+    }
+}